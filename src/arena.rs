@@ -0,0 +1,143 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::{Index, IndexMut};
+
+// Slab storage for the graph based strategies. Values are addressed by typed
+// indices into a vector and the slots of removed values are handed out again
+// from a free list, so following an edge is an array access instead of a
+// hash lookup. Index 0 is never handed out and stands for a missing node.
+
+pub struct Id<T> {
+    index : usize,
+    marker : PhantomData<fn() -> T>
+}
+
+impl<T> Id<T> {
+    pub fn null() -> Id<T> {
+        Id { index: 0, marker: PhantomData }
+    }
+
+    pub fn is_null(self) -> bool {
+        self.index == 0
+    }
+
+    pub fn index(self) -> usize {
+        self.index
+    }
+}
+
+impl<T> Clone for Id<T> {
+    fn clone(&self) -> Id<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Id<T> { }
+
+impl<T> PartialEq for Id<T> {
+    fn eq(&self, other : &Id<T>) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Id<T> { }
+
+impl<T> PartialOrd for Id<T> {
+    fn partial_cmp(&self, other : &Id<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Id<T> {
+    fn cmp(&self, other : &Id<T>) -> Ordering {
+        self.index.cmp(&other.index)
+    }
+}
+
+impl<T> Hash for Id<T> {
+    fn hash<H : Hasher>(&self, state : &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Id<T> {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.index)
+    }
+}
+
+impl<T> fmt::Display for Id<T> {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.index)
+    }
+}
+
+#[derive(Debug)]
+pub struct Arena<T> {
+    slots : Vec<Option<T>>,
+    free : Vec<usize>,
+    len : usize
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Arena<T> {
+        Arena::new()
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Arena<T> {
+        Arena { slots: vec![None], free: vec![], len: 0 }
+    }
+
+    pub fn insert(&mut self, value : T) -> Id<T> {
+        self.len += 1;
+        if let Some(index) = self.free.pop() {
+            self.slots[index] = Some(value);
+            return Id { index, marker: PhantomData };
+        }
+        self.slots.push(Some(value));
+        Id { index: self.slots.len() - 1, marker: PhantomData }
+    }
+
+    pub fn remove(&mut self, id : Id<T>) -> Option<T> {
+        let value = self.slots.get_mut(id.index)?.take()?;
+        self.free.push(id.index);
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn get(&self, id : Id<T>) -> Option<&T> {
+        self.slots.get(id.index)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id : Id<T>) -> Option<&mut T> {
+        self.slots.get_mut(id.index)?.as_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Id<T>, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.as_ref().map(|value| (Id { index, marker: PhantomData }, value))
+        })
+    }
+}
+
+impl<T> Index<Id<T>> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id : Id<T>) -> &T {
+        self.get(id).unwrap_or_else(|| panic!("Dangling index {} into arena.", id.index))
+    }
+}
+
+impl<T> IndexMut<Id<T>> for Arena<T> {
+    fn index_mut(&mut self, id : Id<T>) -> &mut T {
+        self.get_mut(id).unwrap_or_else(|| panic!("Dangling index {} into arena.", id.index))
+    }
+}
@@ -0,0 +1,205 @@
+use std::rc::Rc;
+
+use crate::tree::Tree;
+use crate::strategy::Strategy;
+use crate::trace;
+use crate::value::{self, Neutral};
+
+// A compiler from de Bruijn trees to bytecode for a ZAM style machine, see
+// Leroy "The ZINC experiment". Applications push their arguments right to
+// left on top of a mark, functions take their arguments off the stack with
+// `Grab` and build a partial application closure when they run into the
+// mark.
+
+#[derive(Debug, Clone, Copy)]
+enum Instruction {
+    Access(usize),
+    Closure(usize),
+    Push,
+    PushMark,
+    Apply,
+    AppTerm,
+    Grab,
+    Return,
+    Stop
+}
+
+type Value = value::Value<usize>;
+type Env = value::Env<Value>;
+
+enum Slot {
+    Mark,
+    Value(Value)
+}
+
+struct Compiler<'a> {
+    code : Vec<Instruction>,
+    pending : Vec<(usize, &'a Tree)>
+}
+
+impl<'a> Compiler<'a> {
+    fn spine(tree : &'a Tree) -> (&'a Tree, Vec<&'a Tree>) {
+        let mut args = vec![];
+        let mut head = tree;
+        while let Tree::App(left, right) = head {
+            args.push(&**right);
+            head = left;
+        }
+        // innermost argument last so it is pushed last
+        (head, args)
+    }
+
+    fn compile(&mut self, tree : &'a Tree) {
+        match tree {
+            Tree::Var(index) => self.code.push(Instruction::Access(*index)),
+            Tree::Abs(_, body) => {
+                self.pending.push((self.code.len(), body));
+                self.code.push(Instruction::Closure(0));
+            },
+            Tree::App(_, _) => {
+                let (head, args) = Compiler::spine(tree);
+                self.code.push(Instruction::PushMark);
+                for arg in args {
+                    self.compile(arg);
+                    self.code.push(Instruction::Push);
+                }
+                self.compile(head);
+                self.code.push(Instruction::Apply);
+            }
+        }
+    }
+
+    fn compile_tail(&mut self, tree : &'a Tree) {
+        match tree {
+            Tree::Var(index) => {
+                self.code.push(Instruction::Access(*index));
+                self.code.push(Instruction::Return);
+            },
+            Tree::Abs(_, body) => {
+                self.code.push(Instruction::Grab);
+                self.compile_tail(body);
+            },
+            Tree::App(_, _) => {
+                let (head, args) = Compiler::spine(tree);
+                for arg in args {
+                    self.compile(arg);
+                    self.code.push(Instruction::Push);
+                }
+                self.compile(head);
+                self.code.push(Instruction::AppTerm);
+            }
+        }
+    }
+
+    // Address 0 holds `Stop` so readback can return into it
+    fn program(tree : &'a Tree) -> Vec<Instruction> {
+        let mut compiler = Compiler {
+            code: vec![Instruction::Stop],
+            pending: vec![]
+        };
+        compiler.compile(tree);
+        compiler.code.push(Instruction::Stop);
+        while let Some((index, body)) = compiler.pending.pop() {
+            compiler.code[index] = Instruction::Closure(compiler.code.len());
+            compiler.code.push(Instruction::Grab);
+            compiler.compile_tail(body);
+        }
+        compiler.code
+    }
+}
+
+#[derive(Debug)]
+pub struct Vm {
+    code : Vec<Instruction>,
+    steps : usize
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        Vm { code: vec![Instruction::Stop], steps: 0 }
+    }
+
+    fn run(&mut self, pc : usize, acc : Value, env : Rc<Env>, args : Vec<Slot>) -> Value {
+        let (mut pc, mut acc, mut env, mut args) = (pc, acc, env, args);
+        let mut frames : Vec<(usize, Rc<Env>)> = vec![(0, env.clone())];
+        loop {
+            let instruction = self.code[pc];
+            self.steps += 1;
+            if trace::enabled() {
+                trace::record(&format!("{:?}", instruction), Env::len(&env), args.len(),
+                    || format!("pc {}", pc));
+            }
+            pc += 1;
+            match instruction {
+                Instruction::Access(index) => acc = Env::lookup(&env, index),
+                Instruction::Closure(address) => acc = Value::Closure(address, env.clone()),
+                Instruction::Push => args.push(Slot::Value(acc.clone())),
+                Instruction::PushMark => args.push(Slot::Mark),
+                Instruction::Apply | Instruction::AppTerm | Instruction::Return => {
+                    if let Instruction::Apply = instruction {
+                        frames.push((pc, env.clone()));
+                    } else if let Instruction::Return = instruction {
+                        if let Some(Slot::Mark) = args.last() {
+                            args.pop();
+                            let (p, e) = frames.pop().expect("Impossible machine state.");
+                            pc = p;
+                            env = e;
+                            continue;
+                        }
+                    }
+                    match acc.clone() {
+                        Value::Closure(address, e) => {
+                            pc = address;
+                            env = e;
+                        },
+                        Value::Neutral(mut neutral) => {
+                            while let Some(Slot::Value(arg)) = args.pop() {
+                                neutral = Rc::new(Neutral::App(neutral, arg));
+                            }
+                            acc = Value::Neutral(neutral);
+                            let (p, e) = frames.pop().expect("Impossible machine state.");
+                            pc = p;
+                            env = e;
+                        }
+                    }
+                },
+                Instruction::Grab => {
+                    match args.pop() {
+                        Some(Slot::Value(arg)) => env = Rc::new(Env::Cons(arg, env)),
+                        _ => {
+                            acc = Value::Closure(pc - 1, env);
+                            let (p, e) = frames.pop().expect("Impossible machine state.");
+                            pc = p;
+                            env = e;
+                        }
+                    }
+                },
+                Instruction::Stop => return acc
+            }
+        }
+    }
+}
+
+impl Strategy for Vm {
+    fn build(&mut self, tree : &Tree) {
+        let tree = Tree::fix_indices(tree.clone());
+        self.code = Compiler::program(&tree);
+        self.steps = 0;
+    }
+
+    fn reduce(&mut self) -> Option<u64> {
+        let value = self.run(1, Value::Neutral(Rc::new(Neutral::Var(0))), Rc::new(Env::Nil), vec![]);
+        value::readback(value, 0, &mut |address, env, fresh| {
+            let args = vec![Slot::Mark, Slot::Value(fresh)];
+            self.run(address, Value::Closure(address, env.clone()), env, args)
+        }).convert()
+    }
+
+    fn name(&self) -> String {
+        String::from("zam bytecode")
+    }
+
+    fn steps(&self) -> Option<usize> {
+        Some(self.steps)
+    }
+}
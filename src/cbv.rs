@@ -0,0 +1,109 @@
+use std::rc::Rc;
+
+use crate::tree::Tree;
+use crate::strategy::Strategy;
+use crate::trace;
+use crate::value::{self, Neutral};
+
+// The textbook call-by-value CEK machine over de Bruijn indices. The machine
+// alternates between evaluating a term in an environment and returning a
+// value to the top continuation frame, arguments are always evaluated to a
+// value before they are bound.
+
+type Value<'a> = value::Value<&'a Tree>;
+type Env<'a> = value::Env<Value<'a>>;
+
+enum State<'a> {
+    Eval(&'a Tree, Rc<Env<'a>>),
+    Apply(Value<'a>)
+}
+
+enum Frame<'a> {
+    Arg(&'a Tree, Rc<Env<'a>>),
+    Fun(Value<'a>)
+}
+
+#[derive(Debug)]
+pub struct Cbv {
+    tree : Tree
+}
+
+impl Cbv {
+    pub fn new() -> Cbv {
+        Cbv { tree: Tree::Var(0) }
+    }
+
+    fn run<'a>(tree : &'a Tree, env : Rc<Env<'a>>) -> Value<'a> {
+        let mut stack = vec![];
+        let mut state = State::Eval(tree, env);
+        loop {
+            if trace::enabled() {
+                Cbv::trace(&state, &stack);
+            }
+            state = match state {
+                State::Eval(Tree::Var(index), env) => State::Apply(Env::lookup(&env, *index)),
+                State::Eval(Tree::Abs(_, body), env) => State::Apply(Value::Closure(body, env)),
+                State::Eval(Tree::App(left, right), env) => {
+                    stack.push(Frame::Arg(right, env.clone()));
+                    State::Eval(left, env)
+                },
+                State::Apply(value) => match stack.pop() {
+                    Some(Frame::Arg(tree, env)) => {
+                        stack.push(Frame::Fun(value));
+                        State::Eval(tree, env)
+                    },
+                    Some(Frame::Fun(Value::Closure(body, env))) => {
+                        State::Eval(body, Rc::new(Env::Cons(value, env)))
+                    },
+                    Some(Frame::Fun(Value::Neutral(neutral))) => {
+                        State::Apply(Value::Neutral(Rc::new(Neutral::App(neutral, value))))
+                    },
+                    None => return value
+                }
+            };
+        }
+    }
+
+    fn trace(state : &State, stack : &[Frame]) {
+        match state {
+            State::Eval(tree, env) => {
+                let rule = match tree {
+                    Tree::Var(_) => "var",
+                    Tree::Abs(_, _) => "abs",
+                    Tree::App(_, _) => "app"
+                };
+                trace::record(rule, Env::len(env), stack.len(), || tree.to_indexed_string());
+            },
+            State::Apply(value) => {
+                let rule = match stack.last() {
+                    Some(Frame::Arg(_, _)) => "arg",
+                    Some(Frame::Fun(Value::Closure(_, _))) => "fun",
+                    Some(Frame::Fun(Value::Neutral(_))) => "neutral",
+                    None => "halt"
+                };
+                let (env, control) = match value {
+                    Value::Closure(body, env) => (Env::len(env), format!("<λ{}>", body.to_indexed_string())),
+                    Value::Neutral(_) => (0, String::from("<neutral>"))
+                };
+                trace::record(rule, env, stack.len(), || control);
+            }
+        }
+    }
+}
+
+impl Strategy for Cbv {
+    fn build(&mut self, tree : &Tree) {
+        self.tree = Tree::fix_indices(tree.clone());
+    }
+
+    fn reduce(&mut self) -> Option<u64> {
+        let value = Cbv::run(&self.tree, Rc::new(Env::Nil));
+        value::readback(value, 0, &mut |body, env, fresh| {
+            Cbv::run(body, Rc::new(Env::Cons(fresh, env)))
+        }).convert()
+    }
+
+    fn name(&self) -> String {
+        String::from("cbv cek machine")
+    }
+}
@@ -1,25 +1,61 @@
-use std::collections::{HashMap};
+use std::rc::Rc;
 
-use crate::tree::Tree;
+use crate::tree::{Tree, Code};
 use crate::strategy::Strategy;
+use crate::trace;
+
+// Environments are persistent linked frames, extending one shares the tail
+// with every closure that captured it
+#[derive(Debug)]
+enum Binding {
+    Empty,
+    Frame(usize, Rc<Code>, Environment, Environment)
+}
 
 #[derive(Debug, Clone)]
 struct Environment {
-    map: HashMap<usize, (Tree, Box<Environment>)>
+    head : Rc<Binding>
 }
 
 impl Environment {
     pub fn new() -> Environment {
         Environment {
-            map: HashMap::new()
+            head: Rc::new(Binding::Empty)
+        }
+    }
+
+    fn bind(&self, id : usize, code : Rc<Code>, env : Environment) -> Environment {
+        Environment {
+            head: Rc::new(Binding::Frame(id, code, env, self.clone()))
         }
     }
+
+    fn len(&self) -> usize {
+        let mut result = 0;
+        let mut binding = &self.head;
+        while let Binding::Frame(_, _, _, rest) = &**binding {
+            result += 1;
+            binding = &rest.head;
+        }
+        result
+    }
+
+    fn get(&self, id : usize) -> Option<(Rc<Code>, Environment)> {
+        let mut binding = &self.head;
+        while let Binding::Frame(x, code, env, rest) = &**binding {
+            if *x == id {
+                return Some((code.clone(), env.clone()));
+            }
+            binding = &rest.head;
+        }
+        None
+    }
 }
 
 #[derive(Debug, Clone)]
 enum Frame {
-    Closure(Tree, Environment),
-    Argument(Tree, Environment)
+    Closure(Rc<Code>, Environment),
+    Argument(Rc<Code>, Environment)
 }
 
 type Continuations = Vec<Frame>;
@@ -27,7 +63,8 @@ type Continuations = Vec<Frame>;
 #[derive(Debug, Clone)]
 pub struct Machine {
     fresh : usize,
-    code : (Tree, Option<Environment>),
+    steps : usize,
+    code : (Rc<Code>, Option<Environment>),
     env : Environment,
     cc : Continuations,
     done : bool
@@ -37,7 +74,8 @@ impl Machine {
     pub fn new() -> Machine {
         Machine {
             fresh: 0,
-            code: (Tree::Var(0), None),
+            steps: 0,
+            code: (Rc::new(Code::Var(0)), None),
             env: Environment::new(),
             cc: vec![],
             done: false
@@ -46,58 +84,67 @@ impl Machine {
 
     fn transition(machine : Machine) -> Machine {
         let mut fresh = machine.fresh;
+        let mut steps = machine.steps + 1;
         let code = machine.code;
         let env = machine.env;
         let mut cc = machine.cc;
 
-        match code {
-            (Tree::App(left, right), _) => {
-                cc.push(Frame::Argument(*right, env.clone()));
+        match (&*code.0, code.1) {
+            (Code::App(left, right), None) => {
+                cc.push(Frame::Argument(right.clone(), env.clone()));
                 Machine {
                     fresh,
-                    code: (*left, None),
+                    steps,
+                    code: (left.clone(), None),
                     env,
                     cc,
                     done: false
                 }
             },
-            (Tree::Abs(id, body), None) => {
+            (Code::Abs(_, _), None) => {
                 Machine {
                     fresh,
-                    code: (Tree::Abs(id, body), Some(env.clone())),
+                    steps,
+                    code: (code.0.clone(), Some(env.clone())),
                     env,
                     cc,
                     done: false
                 }
             },
-            (Tree::Abs(id, mut body), Some(e1)) => {
+            // Values are either abstractions or neutral terms built while
+            // reducing under a binder
+            (_, Some(e1)) => {
+                let value = code.0.clone();
                 if let Some(k) = cc.pop() {
                     match k {
-                        Frame::Closure(t, mut e2) => {
-                            if let Tree::Abs(x, mut b) = t {
-                                if e2.map.contains_key(&x) {
-                                    fresh += 1;
-                                    b.rename(x, fresh);
-                                    body.rename(x, fresh);
-                                    e2.map.insert(fresh, (Tree::Abs(id, body), Box::new(e1)));
-                                } else {
-                                    e2.map.insert(x, (Tree::Abs(id, body), Box::new(e1)));
-                                }
+                        Frame::Closure(t, e2) => {
+                            if let Code::Abs(x, b) = &*t {
                                 Machine {
                                     fresh,
-                                    code: (*b, None),
-                                    env: e2,
-                                    cc: cc,
+                                    steps,
+                                    code: (b.clone(), None),
+                                    env: e2.bind(*x, value, e1),
+                                    cc,
                                     done: false
                                 }
                             } else {
-                                panic!("Impossible machine state.");
+                                let arg = Machine::readback(&mut fresh, &mut steps, value, e1);
+                                let code = Rc::new(Code::App(t, arg));
+                                Machine {
+                                    fresh,
+                                    steps,
+                                    code: (code, Some(Environment::new())),
+                                    env,
+                                    cc,
+                                    done: false
+                                }
                             }
                         },
                         Frame::Argument(m, e2) => {
-                            cc.push(Frame::Closure(Tree::Abs(id, body), e1));
+                            cc.push(Frame::Closure(value, e1));
                             Machine {
                                 fresh,
+                                steps,
                                 code: (m, None),
                                 env: e2,
                                 cc,
@@ -108,18 +155,20 @@ impl Machine {
                 } else {
                     Machine {
                         fresh,
-                        code: (Tree::Abs(id, body), Some(e1.clone())),
+                        steps,
+                        code: (value, Some(e1.clone())),
                         env: e1,
                         cc,
                         done: true
                     }
                 }
             },
-            (Tree::Var(v), _) => {
-                let e2 = env.map.get(&v).expect("Impossible machine state.").clone();
+            (Code::Var(v), None) => {
+                let (t, e2) = env.get(*v).expect("Impossible machine state.");
                 Machine {
                     fresh,
-                    code: (e2.0, Some(*e2.1)),
+                    steps,
+                    code: (t, Some(e2)),
                     env,
                     cc,
                     done: false
@@ -128,28 +177,77 @@ impl Machine {
         }
     }
 
+    fn rule(&self) -> &'static str {
+        match (&*self.code.0, &self.code.1, self.cc.last()) {
+            (Code::App(_, _), None, _) => "app",
+            (Code::Abs(_, _), None, _) => "abs",
+            (Code::Var(_), None, _) => "var",
+            (_, Some(_), None) => "halt",
+            (_, Some(_), Some(Frame::Argument(_, _))) => "arg",
+            (_, Some(_), Some(Frame::Closure(t, _))) => {
+                if let Code::Abs(_, _) = **t { "bind" } else { "neutral" }
+            }
+        }
+    }
+
     fn reduce(machine : Machine) -> Machine {
         let mut result = machine;
         while !result.done {
+            if trace::enabled() {
+                let env = result.code.1.as_ref().unwrap_or(&result.env);
+                trace::record(result.rule(), env.len(), result.cc.len(),
+                    || result.code.0.to_tree().to_string());
+            }
             result = Machine::transition(result);
         }
         result
     }
+
+    // Strong reduction: the bound variable of an abstraction value is mapped
+    // to a fresh neutral variable and the body is run to a value again
+    fn readback(fresh : &mut usize, steps : &mut usize, value : Rc<Code>, env : Environment) -> Rc<Code> {
+        if let Code::Abs(x, body) = &*value {
+            *fresh += 1;
+            let y = *fresh;
+            let neutral = Rc::new(Code::Var(y));
+            let machine = Machine::reduce(Machine {
+                fresh: *fresh,
+                steps: *steps,
+                code: (body.clone(), None),
+                env: env.bind(*x, neutral, Environment::new()),
+                cc: vec![],
+                done: false
+            });
+            *fresh = machine.fresh;
+            *steps = machine.steps;
+            let (value, env) = machine.code;
+            let env = env.expect("Impossible machine state.");
+            Rc::new(Code::Abs(y, Machine::readback(fresh, steps, value, env)))
+        } else {
+            value
+        }
+    }
 }
 
 impl Strategy for Machine {
     fn build(&mut self, tree : &Tree) {
+        *self = Machine::new();
         self.fresh = Tree::find_largest_var(tree);
-        self.code = (tree.clone(), None);
+        self.code = (Code::from(tree), None);
     }
 
     fn reduce(&mut self) -> Option<u64> {
         *self = Machine::reduce(self.clone());
-        //println!("{:?}", self);
-        self.code.0.convert()
+        let (value, env) = self.code.clone();
+        let env = env.expect("Impossible machine state.");
+        Machine::readback(&mut self.fresh, &mut self.steps, value, env).to_tree().convert()
     }
 
     fn name(&self) -> String {
         String::from("cek machine")
     }
+
+    fn steps(&self) -> Option<usize> {
+        Some(self.steps)
+    }
 }
@@ -0,0 +1,79 @@
+use std::rc::Rc;
+
+use crate::tree::Tree;
+use crate::strategy::Strategy;
+use crate::value::{self, Neutral};
+
+// Closure generation: every de Bruijn tree is compiled once into nested Rust
+// closures that take the environment and fetch variables by their index, so
+// reducing never walks the syntax tree or looks a variable up by name. The
+// environment is the shared linked list, fetching walks it slot by slot and
+// only the innermost variable is read directly.
+
+type Function = dyn Fn(&Rc<Env>) -> Value;
+
+// Compiled code is the closure payload of its own values, so it needs a type
+// of its own rather than an alias
+#[derive(Clone)]
+struct Code(Rc<Function>);
+
+type Value = value::Value<Code>;
+type Env = value::Env<Value>;
+
+pub struct Compiled {
+    code : Code
+}
+
+impl Compiled {
+    pub fn new() -> Compiled {
+        Compiled { code: Compiled::compile(&Tree::Var(0)) }
+    }
+
+    fn compile(tree : &Tree) -> Code {
+        match tree {
+            Tree::Var(index) => {
+                let index = *index;
+                match index {
+                    0 => Code(Rc::new(|env : &Rc<Env>| match &**env {
+                        Env::Cons(value, _) => value.clone(),
+                        Env::Nil => panic!("Free variables are not supported.")
+                    })),
+                    _ => Code(Rc::new(move |env : &Rc<Env>| Env::lookup(env, index)))
+                }
+            },
+            Tree::Abs(_, body) => {
+                let body = Compiled::compile(body);
+                Code(Rc::new(move |env : &Rc<Env>| Value::Closure(body.clone(), env.clone())))
+            },
+            Tree::App(left, right) => {
+                let left = Compiled::compile(left);
+                let right = Compiled::compile(right);
+                Code(Rc::new(move |env : &Rc<Env>| Compiled::apply((left.0)(env), (right.0)(env))))
+            }
+        }
+    }
+
+    fn apply(function : Value, argument : Value) -> Value {
+        match function {
+            Value::Closure(body, env) => (body.0)(&Rc::new(Env::Cons(argument, env))),
+            Value::Neutral(neutral) => Value::Neutral(Rc::new(Neutral::App(neutral, argument)))
+        }
+    }
+}
+
+impl Strategy for Compiled {
+    fn build(&mut self, tree : &Tree) {
+        self.code = Compiled::compile(&Tree::fix_indices(tree.clone()));
+    }
+
+    fn reduce(&mut self) -> Option<u64> {
+        let value = (self.code.0)(&Rc::new(Env::Nil));
+        value::readback(value, 0, &mut |body, env, fresh| {
+            Compiled::apply(Value::Closure(body, env), fresh)
+        }).convert()
+    }
+
+    fn name(&self) -> String {
+        String::from("closure compilation")
+    }
+}
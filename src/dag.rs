@@ -1,9 +1,13 @@
-use std::collections::{HashMap};
+use std::collections::{HashMap, HashSet};
+use std::mem;
 
+use crate::arena::{Arena, Id};
 use crate::tree::Tree;
 use crate::strategy::Strategy;
+use crate::trace;
+use crate::snapshot;
 
-fn remove_item(item : usize, vec : &mut Vec<usize>) {
+fn remove_item(item : NodeId, vec : &mut Vec<NodeId>) {
     let mut index = 0;
     let mut found = false;
     for i in 0..vec.len() {
@@ -24,25 +28,27 @@ enum Kind {
     App
 }
 
+type NodeId = Id<Node>;
+
 #[derive(Debug)]
 struct Node {
     kind : Kind,
-    parents : Vec<usize>,
-    left : usize,
-    right : usize
+    parents : Vec<NodeId>,
+    left : NodeId,
+    right : NodeId
 }
 
 impl Node {
-    fn var(parents : Vec<usize>) -> Node {
+    fn var(parents : Vec<NodeId>) -> Node {
         Node {
             kind: Kind::Var,
             parents,
-            left: 0,
-            right: 0
+            left: NodeId::null(),
+            right: NodeId::null()
         }
     }
 
-    fn abs(left : usize, right : usize, parents : Vec<usize>) -> Node {
+    fn abs(left : NodeId, right : NodeId, parents : Vec<NodeId>) -> Node {
         Node {
             kind: Kind::Abs,
             parents,
@@ -51,7 +57,7 @@ impl Node {
         }
     }
 
-    fn app(left : usize, right : usize, parents : Vec<usize>) -> Node {
+    fn app(left : NodeId, right : NodeId, parents : Vec<NodeId>) -> Node {
         Node {
             kind: Kind::App,
             parents,
@@ -61,60 +67,211 @@ impl Node {
     }
 }
 
+// An edge into a node, either the root or a side of its parent
+#[derive(Debug, Copy, Clone)]
+enum Edge {
+    Root,
+    Left(NodeId),
+    Right(NodeId)
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Visit {
+    Node(Edge),
+    Contract(Edge)
+}
+
+// The de Bruijn shape of a subterm with its children already interned
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Shape {
+    Bound(usize),
+    Free(NodeId),
+    Abs(usize),
+    App(usize, usize)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Order {
+    Outermost,
+    Innermost
+}
+
 #[derive(Debug)]
 pub struct Dag {
-    id : usize,
-    nodes : HashMap<usize, Node>
+    root : NodeId,
+    order : Order,
+    sharing : bool,
+    steps : usize,
+    peak : usize,
+    nodes : Arena<Node>
 }
 
 impl Dag {
     pub fn new() -> Dag {
-        Dag { id: 0, nodes: HashMap::new() }
+        Dag { root: NodeId::null(), order: Order::Outermost, sharing: false, steps: 0,
+            peak: 0, nodes: Arena::new() }
+    }
+
+    pub fn with_order(order : Order) -> Dag {
+        Dag { order, ..Dag::new() }
     }
 
-    fn insert(&mut self, node : Node) -> usize {
-        self.id += 1;
-        self.nodes.insert(self.id, node);
-        self.id
+    pub fn hash_consed() -> Dag {
+        Dag { sharing: true, ..Dag::new() }
     }
 
-    fn reserve(dag : &mut Dag, mut continuation : impl FnMut(&mut Dag, usize) -> Node) -> usize {
-        dag.id += 1;
-        let reserved = dag.id;
+    fn insert(&mut self, node : Node) -> NodeId {
+        self.nodes.insert(node)
+    }
+
+    fn reserve(dag : &mut Dag, mut continuation : impl FnMut(&mut Dag, NodeId) -> Node) -> NodeId {
+        let reserved = dag.insert(Node::var(vec![]));
         let node = continuation(dag, reserved);
-        dag.nodes.insert(reserved, node);
+        dag.nodes[reserved] = node;
         reserved
     }
 
-    fn remove(&mut self, id : usize) -> Option<Node> {
-        self.nodes.remove(&id)
+    fn remove(&mut self, id : NodeId) -> Option<Node> {
+        self.nodes.remove(id)
     }
 
-    fn get_mut(&mut self, id : usize) -> Option<&mut Node> {
-        self.nodes.get_mut(&id)
+    fn get_mut(&mut self, id : NodeId) -> Option<&mut Node> {
+        self.nodes.get_mut(id)
     }
 
     pub fn from(tree : &Tree) -> Dag {
         let mut dag = Dag::new();
         let mut map = HashMap::new();
-        dag.from_helper(tree, vec![], &mut map);
-        dag.fix_abs_refs(&mut map);
+        dag.root = dag.from_helper(tree, vec![], &mut map);
+        dag.peak = dag.nodes.len();
         dag
     }
 
-    fn fix_abs_refs(&mut self, map : &mut HashMap<usize, usize>) {
-        for (_, node) in self.nodes.iter_mut() {
-            if node.kind == Kind::Abs {
-                if let Some(v) = map.get(&node.left) {
-                    node.left = *v;
-                } else {
-                    node.left = 0;
+    // Like `from` but alpha equivalent closed subterms are built only once
+    pub fn from_shared(tree : &Tree) -> Dag {
+        let mut dag = Dag::from(tree);
+        let mut shapes = HashMap::new();
+        let mut closed = HashMap::new();
+        dag.share(dag.root, &mut vec![], &mut shapes, &mut closed);
+        dag.peak = dag.nodes.len();
+        dag
+    }
+
+    // Interns the shape of every subterm bottom up and merges each closed
+    // subterm into the first one seen with the same shape. Returns the
+    // interned shape and how many enclosing binders the subterm refers to.
+    // Only variables are shared before this runs, so the binders on the way
+    // down determine every de Bruijn index.
+    fn share(&mut self, id : NodeId, binders : &mut Vec<NodeId>,
+        shapes : &mut HashMap<Shape, usize>, closed : &mut HashMap<usize, NodeId>) -> (usize, usize)
+    {
+        let (kind, left, right) = {
+            let node = &self.nodes[id];
+            (node.kind, node.left, node.right)
+        };
+        let (shape, scope) = match kind {
+            Kind::Var => {
+                match binders.iter().rev().position(|x| *x == id) {
+                    Some(index) => (Shape::Bound(index), index + 1),
+                    None => (Shape::Free(id), usize::MAX)
                 }
+            },
+            Kind::Abs => {
+                binders.push(left);
+                let (body, scope) = self.share(right, binders, shapes, closed);
+                binders.pop();
+                (Shape::Abs(body), scope.saturating_sub(1))
+            },
+            Kind::App => {
+                let (left, left_scope) = self.share(left, binders, shapes, closed);
+                let (right, right_scope) = self.share(right, binders, shapes, closed);
+                (Shape::App(left, right), left_scope.max(right_scope))
+            }
+        };
+        let next = shapes.len();
+        let shape = *shapes.entry(shape).or_insert(next);
+        if scope == 0 {
+            match closed.get(&shape) {
+                Some(canonical) => self.merge(id, *canonical),
+                None => { closed.insert(shape, id); }
             }
         }
+        (shape, scope)
     }
 
-    fn from_helper(&mut self, tree : &Tree, parents : Vec<usize>, map : &mut HashMap<usize, usize>) -> usize {
+    // Points every parent of `id` at `to` and frees what is left of `id`
+    fn merge(&mut self, id : NodeId, to : NodeId) {
+        let parents = mem::take(&mut self.get_mut(id).expect("Dangling node in dag.").parents);
+        for parent in parents.iter() {
+            self.redirect(*parent, id, to);
+        }
+        if self.root == id {
+            self.root = to;
+        }
+        self.get_mut(to).expect("Dangling node in dag.").parents.extend(parents);
+        self.release(id);
+    }
+
+    // Unfolds sharing, variables are named by the id of their node and
+    // binders without a variable node by their own id. Fails if the walk
+    // from the root runs into a node which has been removed.
+    pub fn to_tree(&self) -> Option<Tree> {
+        self.to_tree_helper(self.root)
+    }
+
+    fn to_tree_helper(&self, id : NodeId) -> Option<Tree> {
+        let node = self.nodes.get(id)?;
+        match node.kind {
+            Kind::Var => Some(Tree::Var(id.index())),
+            Kind::Abs => {
+                let binder = if node.left.is_null() { id } else { node.left }.index();
+                let body = self.to_tree_helper(node.right)?;
+                Some(Tree::Abs(binder, Box::new(body)))
+            },
+            Kind::App => {
+                let left = self.to_tree_helper(node.left)?;
+                let right = self.to_tree_helper(node.right)?;
+                Some(Tree::App(Box::new(left), Box::new(right)))
+            }
+        }
+    }
+
+    // Graphviz rendering of the live nodes. Child edges are solid, binders
+    // point at their variable with a dashed edge and every uplink is drawn
+    // as a dotted gray edge back to the parent.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph dag {\n    node [fontname=\"monospace\"];\n");
+        out.push_str("    root [shape=point];\n");
+        out.push_str(&format!("    root -> n{};\n", self.root));
+        for (id, node) in self.nodes.iter() {
+            match node.kind {
+                Kind::Var => {
+                    out.push_str(&format!("    n{} [label=\"x{}\", shape=plaintext];\n", id, id));
+                },
+                Kind::Abs => {
+                    out.push_str(&format!("    n{} [label=\"λ\", shape=circle];\n", id));
+                    out.push_str(&format!("    n{} -> n{};\n", id, node.right));
+                    if !node.left.is_null() {
+                        out.push_str(&format!(
+                            "    n{} -> n{} [style=dashed, constraint=false];\n", id, node.left));
+                    }
+                },
+                Kind::App => {
+                    out.push_str(&format!("    n{} [label=\"@\", shape=circle];\n", id));
+                    out.push_str(&format!("    n{} -> n{} [label=\"fun\"];\n", id, node.left));
+                    out.push_str(&format!("    n{} -> n{} [label=\"arg\"];\n", id, node.right));
+                }
+            }
+            for parent in node.parents.iter() {
+                out.push_str(&format!(
+                    "    n{} -> n{} [color=gray, style=dotted, constraint=false];\n", id, parent));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn from_helper(&mut self, tree : &Tree, parents : Vec<NodeId>, map : &mut HashMap<usize, NodeId>) -> NodeId {
         match tree {
             Tree::Var(id) => {
                 if let Some(v) = map.get(id) {
@@ -129,10 +286,17 @@ impl Dag {
                 }
             },
             Tree::Abs(id, body) => {
-                Dag::reserve(self, |dag, abs_id| {
+                // binders are scoped, an inner binder shadows the outer one
+                let shadowed = map.remove(id);
+                let abs_id = Dag::reserve(self, |dag, abs_id| {
                     let body_id = dag.from_helper(body, vec![abs_id], map);
-                    Node::abs(*id, body_id, parents.clone())
-                })
+                    let var_id = map.remove(id).unwrap_or_else(NodeId::null);
+                    Node::abs(var_id, body_id, parents.clone())
+                });
+                if let Some(v) = shadowed {
+                    map.insert(*id, v);
+                }
+                abs_id
             },
             Tree::App(left, right) => {
                 Dag::reserve(self, |dag, app_id| {
@@ -144,103 +308,292 @@ impl Dag {
         }
     }
 
-    fn step(&mut self, id : usize) {
-        let app = if let Some(app) = self.remove(id) { app } else { return; };
-        let lam = if let Some(lam) = self.remove(app.left) { lam } else { return; };
-        let varid = lam.left;
-
-        // handle argument part
-        let argid = app.right;
-        if varid != 0 {
-            let mut var = if let Some(var) = self.remove(varid) { var }
-                else { return; };
-            remove_item(id, &mut var.parents);
-            remove_item(app.left, &mut var.parents);
-            for i in var.parents.iter() {
-                let node = if let Some(node) = self.get_mut(*i) { node }
-                    else { return; };
-                if node.left == varid {
-                    node.left = argid;
-                }
-                if node.right == varid {
-                    node.right = argid;
-                }
+    // Points every edge of `parent` into `from` at `to` instead
+    fn redirect(&mut self, parent : NodeId, from : NodeId, to : NodeId) {
+        let node = self.get_mut(parent).expect("Dangling parent in dag.");
+        if node.left == from && node.kind != Kind::Abs {
+            node.left = to;
+        }
+        if node.right == from {
+            node.right = to;
+        }
+    }
+
+    // Replaces node `id` by node `to` in every parent and at the root
+    fn replace(&mut self, id : NodeId, to : NodeId) {
+        let node = self.remove(id).expect("Dangling node in dag.");
+        for parent in node.parents.iter() {
+            self.redirect(*parent, id, to);
+        }
+        if self.root == id {
+            self.root = to;
+        }
+        let target = self.get_mut(to).expect("Dangling node in dag.");
+        target.parents.extend(node.parents);
+    }
+
+    // Contracts the redex at `id` and returns the node which replaced it.
+    // An abstraction used only by this redex is instantiated in place,
+    // a shared one is left alone and its body is copied instead, see Shivers
+    // and Wand "Bottom-up β-reduction: uplinks and λ-DAGs".
+    fn step(&mut self, id : NodeId) -> NodeId {
+        let (lamid, argid) = {
+            let app = self.nodes.get(id).expect("Dangling redex in dag.");
+            (app.left, app.right)
+        };
+        let lam = self.get_mut(lamid).expect("Dangling abstraction in dag.");
+        remove_item(id, &mut lam.parents);
+        let shared = !lam.parents.is_empty() || lamid == argid;
+        let arg = self.get_mut(argid).expect("Dangling argument in dag.");
+        remove_item(id, &mut arg.parents);
+
+        let result = if shared {
+            self.upcopy(lamid, argid)
+        } else {
+            self.instantiate(lamid, argid)
+        };
+        self.replace(id, result);
+        self.release(lamid);
+        self.release(argid);
+
+        self.steps += 1;
+        self.peak = self.peak.max(self.nodes.len());
+        let rule = if shared { "copy" } else { "beta" };
+        if trace::enabled() {
+            trace::record_nodes(rule, self.nodes.len(), || format!("redex {}", id));
+        }
+        snapshot::record(rule, Some(id.index()), || self.to_dot());
+        result
+    }
+
+    // Frees `id` if nothing points at it any more, along with every node
+    // only reachable through it. The parent lists act as reference counts.
+    // Variables belong to their binder and are only freed along with it.
+    fn release(&mut self, id : NodeId) {
+        let mut stack = vec![id];
+        let mut vars = vec![];
+        while let Some(id) = stack.pop() {
+            let free = match self.nodes.get(id) {
+                Some(node) => node.parents.is_empty() && id != self.root
+                    && node.kind != Kind::Var,
+                None => false
+            };
+            if !free { continue; }
+            let node = self.remove(id).expect("impossible");
+            let children = match node.kind {
+                Kind::Var => vec![],
+                Kind::Abs => vec![node.right],
+                Kind::App => vec![node.left, node.right]
+            };
+            for child in children {
+                let child_node = self.get_mut(child).expect("Dangling node in dag.");
+                remove_item(id, &mut child_node.parents);
+                stack.push(child);
             }
-            if self.nodes.get(&argid).is_some() {
-                let arg = if let Some(arg) = self.get_mut(argid) { arg }
-                    else {return; }; 
-                remove_item(id, &mut arg.parents);
-                arg.parents.extend(var.parents);
+            if node.kind == Kind::Abs && !node.left.is_null() {
+                vars.push(node.left);
             }
-        } else {
-            let arg = self.nodes.remove(&argid);
-            if let Some(arg) = arg {
-                let mut stack = vec![arg];
-                while let Some(node) = stack.pop() {
-                    let left = self.nodes.remove(&node.left);
-                    let right = self.nodes.remove(&node.right);
-                    if let Some(left) = left { stack.push(left); }
-                    if let Some(right) = right { stack.push(right); }
-                }
+        }
+        for var in vars {
+            if self.nodes[var].parents.is_empty() {
+                self.remove(var);
             }
         }
+    }
 
-        // handle function part
-        let bodyid = lam.right;
-        for i in app.parents.iter() {
-            let node = if let Some(node) = self.get_mut(*i) { node }
-                else { return; };
-            if node.left == id {
-                node.left = bodyid;
-            }
-            if node.right == id {
-                node.right = bodyid;
+    fn instantiate(&mut self, lamid : NodeId, argid : NodeId) -> NodeId {
+        let lam = self.remove(lamid).expect("Dangling abstraction in dag.");
+        let (varid, bodyid) = (lam.left, lam.right);
+        let body = self.get_mut(bodyid).expect("Dangling body in dag.");
+        remove_item(lamid, &mut body.parents);
+        if varid.is_null() {
+            return bodyid;
+        }
+        let var = self.remove(varid).expect("Dangling variable in dag.");
+        for parent in var.parents.iter() {
+            self.redirect(*parent, varid, argid);
+        }
+        let arg = self.get_mut(argid).expect("Dangling argument in dag.");
+        arg.parents.extend(var.parents);
+        if bodyid == varid { argid } else { bodyid }
+    }
+
+    // Copies the nodes of the body on a path from the bound variable up to
+    // the abstraction with the variable replaced by the argument. Copied
+    // abstractions get a fresh variable, so the paths from their own
+    // variable are copied as well. Everything else is shared.
+    fn upcopy(&mut self, lamid : NodeId, argid : NodeId) -> NodeId {
+        let (varid, bodyid) = {
+            let lam = self.nodes.get(lamid).expect("Dangling abstraction in dag.");
+            (lam.left, lam.right)
+        };
+        if varid.is_null() {
+            return bodyid;
+        }
+        let mut copies = HashMap::new();
+        copies.insert(varid, argid);
+        let mut spine = HashSet::new();
+        let mut stack = vec![varid];
+        while let Some(id) = stack.pop() {
+            let parents = self.nodes[id].parents.clone();
+            for parent in parents {
+                if parent == lamid || !spine.insert(parent) { continue; }
+                stack.push(parent);
+                let node = &self.nodes[parent];
+                if node.kind == Kind::Abs && !node.left.is_null() {
+                    let var = node.left;
+                    copies.insert(var, self.insert(Node::var(vec![])));
+                    stack.push(var);
+                }
             }
         }
-        
-        if self.nodes.get(&bodyid).is_some() {
-            let body = if let Some(body) = self.get_mut(bodyid) { body }
-                else { return; };
-            remove_item(app.left, &mut body.parents);
-            body.parents.extend(app.parents);
+        self.copy(bodyid, &spine, &mut copies)
+    }
+
+    fn copy(&mut self, id : NodeId, spine : &HashSet<NodeId>, copies : &mut HashMap<NodeId, NodeId>) -> NodeId {
+        if let Some(copy) = copies.get(&id) {
+            return *copy;
+        }
+        if !spine.contains(&id) {
+            return id;
         }
+        let (kind, left, right) = {
+            let node = &self.nodes[id];
+            (node.kind, node.left, node.right)
+        };
+        let copy = match kind {
+            Kind::Abs => {
+                let var = copies.get(&left).cloned().unwrap_or_else(NodeId::null);
+                let body = self.copy(right, spine, copies);
+                let copy = self.insert(Node::abs(var, body, vec![]));
+                self.get_mut(body).expect("impossible").parents.push(copy);
+                copy
+            },
+            Kind::App => {
+                let left = self.copy(left, spine, copies);
+                let right = self.copy(right, spine, copies);
+                let copy = self.insert(Node::app(left, right, vec![]));
+                self.get_mut(left).expect("impossible").parents.push(copy);
+                self.get_mut(right).expect("impossible").parents.push(copy);
+                copy
+            },
+            Kind::Var => unreachable!()
+        };
+        copies.insert(id, copy);
+        copy
     }
 
-    fn find_redex(&self) -> Option<usize> {
-        for (id, node) in self.nodes.iter() {
-            if node.kind == Kind::App {
-                let left = self.nodes.get(&node.left);
-                if let Some(left) = left {
-                    if left.kind == Kind::Abs {
-                        return Some(*id);
+    fn child(&self, edge : Edge) -> NodeId {
+        match edge {
+            Edge::Root => self.root,
+            Edge::Left(parent) => self.nodes[parent].left,
+            Edge::Right(parent) => self.nodes[parent].right
+        }
+    }
+
+    fn is_redex(&self, id : NodeId) -> bool {
+        let node = &self.nodes[id];
+        node.kind == Kind::App && self.nodes[node.left].kind == Kind::Abs
+    }
+
+    // Leftmost outermost: contract head redexes until the subterm is a head
+    // normal form, then queue its body or arguments, leftmost popped first.
+    // Queued edges hang off abstractions and neutral applications, which are
+    // never contracted, so they stay valid while other subterms are reduced.
+    fn outermost(&mut self) {
+        let mut work = vec![Edge::Root];
+        while let Some(edge) = work.pop() {
+            let mut spine = vec![];
+            let mut id = self.child(edge);
+            loop {
+                let node = &self.nodes[id];
+                match node.kind {
+                    Kind::App => {
+                        spine.push(id);
+                        id = node.left;
+                    },
+                    Kind::Abs => {
+                        if let Some(redex) = spine.pop() {
+                            id = self.step(redex);
+                        } else {
+                            work.push(Edge::Right(id));
+                            break;
+                        }
+                    },
+                    Kind::Var => {
+                        work.extend(spine.into_iter().map(Edge::Right));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    // Leftmost innermost: both sides of an application are normalized before
+    // it is contracted, and the contractum is normalized again
+    fn innermost(&mut self) {
+        let mut work = vec![Visit::Node(Edge::Root)];
+        while let Some(visit) = work.pop() {
+            match visit {
+                Visit::Node(edge) => {
+                    let id = self.child(edge);
+                    match self.nodes[id].kind {
+                        Kind::App => {
+                            work.push(Visit::Contract(edge));
+                            work.push(Visit::Node(Edge::Right(id)));
+                            work.push(Visit::Node(Edge::Left(id)));
+                        },
+                        Kind::Abs => work.push(Visit::Node(Edge::Right(id))),
+                        Kind::Var => { }
+                    }
+                },
+                Visit::Contract(edge) => {
+                    let id = self.child(edge);
+                    if self.is_redex(id) {
+                        self.step(id);
+                        work.push(Visit::Node(edge));
                     }
                 }
             }
         }
-        None
     }
 
     fn reduce(&mut self) {
-        loop {
-            let redex = self.find_redex();
-            if let Some(redex) = redex {
-                self.step(redex);
-            } else { break; }
+        snapshot::record("initial", None, || self.to_dot());
+        match self.order {
+            Order::Outermost => self.outermost(),
+            Order::Innermost => self.innermost()
         }
     }
 }
 
 impl Strategy for Dag {
     fn build(&mut self, tree : &Tree) {
-        *self = Dag::from(tree);
+        let (order, sharing) = (self.order, self.sharing);
+        *self = if sharing { Dag::from_shared(tree) } else { Dag::from(tree) };
+        self.order = order;
+        self.sharing = sharing;
     }
 
     fn reduce(&mut self) -> Option<u64> {
         self.reduce();
-        Some(0)
+        self.to_tree()?.convert()
     }
 
     fn name(&self) -> String {
-        String::from("dag")
+        match (self.order, self.sharing) {
+            (Order::Outermost, false) => String::from("dag"),
+            (Order::Innermost, false) => String::from("dag (innermost)"),
+            (Order::Outermost, true) => String::from("dag (hash consed)"),
+            (Order::Innermost, true) => String::from("dag (hash consed, innermost)")
+        }
+    }
+
+    fn steps(&self) -> Option<usize> {
+        Some(self.steps)
+    }
+
+    fn nodes(&self) -> Option<usize> {
+        Some(self.peak)
     }
 }
@@ -0,0 +1,176 @@
+use std::rc::Rc;
+
+use crate::tree::Tree;
+use crate::strategy::Strategy;
+
+// A lambda-sigma style calculus of explicit substitutions over de Bruijn
+// indices, see Abadi, Cardelli, Curien and Lévy "Explicit Substitutions".
+// Substitutions are only pushed through the term when the head of the
+// current redex needs to be inspected, everything else stays suspended
+// in a closure until read back.
+
+#[derive(Debug)]
+enum Term {
+    Var(usize),
+    Abs(Rc<Term>),
+    App(Rc<Term>, Rc<Term>),
+    Clos(Rc<Term>, Rc<Subst>)
+}
+
+#[derive(Debug)]
+enum Subst {
+    Id,
+    Shift,
+    Cons(Rc<Term>, Rc<Subst>),
+    Comp(Rc<Subst>, Rc<Subst>)
+}
+
+impl Term {
+    fn from(tree : &Tree) -> Rc<Term> {
+        match tree {
+            Tree::Var(index) => Rc::new(Term::Var(*index)),
+            Tree::Abs(_, body) => Rc::new(Term::Abs(Term::from(body))),
+            Tree::App(left, right) => Rc::new(Term::App(Term::from(left), Term::from(right)))
+        }
+    }
+}
+
+impl Subst {
+    // Composition with the usual simplifications applied eagerly:
+    // id ∘ s = s, s ∘ id = s, ↑ ∘ (a · s) = s and (s ∘ t) ∘ u = s ∘ (t ∘ u)
+    fn compose(left : Rc<Subst>, right : Rc<Subst>) -> Rc<Subst> {
+        match (&*left, &*right) {
+            (Subst::Id, _) => right,
+            (_, Subst::Id) => left,
+            (Subst::Shift, Subst::Cons(_, rest)) => rest.clone(),
+            (Subst::Comp(s, t), _) => Subst::compose(s.clone(), Subst::compose(t.clone(), right)),
+            _ => Rc::new(Subst::Comp(left, right))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sigma {
+    term : Rc<Term>
+}
+
+impl Sigma {
+    pub fn new() -> Sigma {
+        Sigma {
+            term: Rc::new(Term::Var(0))
+        }
+    }
+
+    // n[s], producing a term that may still carry a closure
+    fn lookup(&self, mut index : usize, subst : &Rc<Subst>) -> Rc<Term> {
+        let mut subst = subst.clone();
+        loop {
+            subst = match &*subst {
+                Subst::Id => return Rc::new(Term::Var(index)),
+                Subst::Shift => return Rc::new(Term::Var(index + 1)),
+                Subst::Cons(term, rest) => {
+                    if index == 0 { return term.clone(); }
+                    index -= 1;
+                    rest.clone()
+                },
+                Subst::Comp(left, right) => {
+                    let term = self.lookup(index, left);
+                    return Rc::new(Term::Clos(term, right.clone()));
+                }
+            };
+        }
+    }
+
+    // Weak head normal form, the result is either an abstraction or a
+    // variable applied to unevaluated arguments
+    fn whnf(&self, term : Rc<Term>) -> Rc<Term> {
+        let mut stack = vec![];
+        let mut term = term;
+        loop {
+            term = match &*term {
+                Term::Var(_) => break,
+                Term::Abs(body) => {
+                    if let Some(arg) = stack.pop() {
+                        let subst = Rc::new(Subst::Cons(arg, Rc::new(Subst::Id)));
+                        Rc::new(Term::Clos(body.clone(), subst))
+                    } else {
+                        return term;
+                    }
+                },
+                Term::App(left, right) => {
+                    stack.push(right.clone());
+                    left.clone()
+                },
+                Term::Clos(inner, subst) => {
+                    match &**inner {
+                        Term::Var(index) => self.lookup(*index, subst),
+                        Term::Abs(body) => {
+                            if let Some(arg) = stack.pop() {
+                                // (λa)[s] b → a[b · s]
+                                let subst = Rc::new(Subst::Cons(arg, subst.clone()));
+                                Rc::new(Term::Clos(body.clone(), subst))
+                            } else {
+                                // (λa)[s] → λ(a[0 · (s ∘ ↑)])
+                                let lifted = Subst::compose(subst.clone(), Rc::new(Subst::Shift));
+                                let subst = Rc::new(Subst::Cons(Rc::new(Term::Var(0)), lifted));
+                                return Rc::new(Term::Abs(Rc::new(Term::Clos(body.clone(), subst))));
+                            }
+                        },
+                        Term::App(left, right) => {
+                            stack.push(Rc::new(Term::Clos(right.clone(), subst.clone())));
+                            Rc::new(Term::Clos(left.clone(), subst.clone()))
+                        },
+                        Term::Clos(term, first) => {
+                            Rc::new(Term::Clos(term.clone(), Subst::compose(first.clone(), subst.clone())))
+                        }
+                    }
+                }
+            };
+        }
+        while let Some(arg) = stack.pop() {
+            term = Rc::new(Term::App(term, arg));
+        }
+        term
+    }
+
+    fn normalize(&self, term : Rc<Term>, depth : usize) -> Tree {
+        let term = self.whnf(term);
+        match &*term {
+            Term::Abs(body) => {
+                Tree::Abs(depth, Box::new(self.normalize(body.clone(), depth + 1)))
+            },
+            _ => {
+                let mut args = vec![];
+                let mut head = term;
+                while let Term::App(left, right) = &*head {
+                    args.push(right.clone());
+                    let left = left.clone();
+                    head = left;
+                }
+                let mut result = match &*head {
+                    Term::Var(index) => Tree::Var(*index),
+                    _ => panic!("Impossible weak head normal form.")
+                };
+                while let Some(arg) = args.pop() {
+                    result = Tree::App(Box::new(result), Box::new(self.normalize(arg, depth)));
+                }
+                result
+            }
+        }
+    }
+}
+
+impl Strategy for Sigma {
+    fn build(&mut self, tree : &Tree) {
+        self.term = Term::from(&Tree::fix_indices(tree.clone()));
+    }
+
+    fn reduce(&mut self) -> Option<u64> {
+        let tree = self.normalize(self.term.clone(), 0);
+        tree.convert()
+    }
+
+    fn name(&self) -> String {
+        String::from("explicit substitution")
+    }
+}
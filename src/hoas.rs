@@ -1,9 +1,7 @@
 use std::rc::Rc;
-use std::cell::RefCell;
 use std::fmt::{Display, Error, Formatter};
-use std::collections::HashMap;
 
-use crate::tree::Tree;
+use crate::tree::{Tree, Code};
 use crate::strategy::Strategy;
 
 // inspiration for this taken from:
@@ -32,6 +30,27 @@ impl Clone for Box<dyn HoasFn> {
     }
 }
 
+// Every abstraction captures the scope it was converted in and extends its
+// own copy when applied, so instances of the same binder never see each
+// other's arguments
+enum Scope {
+    Nil,
+    Bind(usize, Hoas, Rc<Scope>)
+}
+
+impl Scope {
+    fn lookup(scope : &Rc<Scope>, id : usize) -> Option<Hoas> {
+        let mut scope = scope;
+        while let Scope::Bind(x, term, rest) = &**scope {
+            if *x == id {
+                return Some(term.clone());
+            }
+            scope = rest;
+        }
+        None
+    }
+}
+
 #[derive(Clone)]
 pub enum Hoas {
     Var(isize),
@@ -52,39 +71,89 @@ impl Hoas {
         Self::lam(|x| Self::app(x.clone(), x))
     }
 
-    fn step(term : Self) -> Self {
+    fn whnf(term : Self) -> Self {
         use Hoas::*;
-        match term {
-            Var(_) => term,
+        let mut term = term;
+        loop {
+            term = match term {
+                App(f, arg) => match Hoas::whnf(*f) {
+                    Abs(body) => body.apply(*arg),
+                    t => return Hoas::app(t, *arg)
+                },
+                t => return t
+            };
+        }
+    }
+
+    // Reduces under abstractions as well. The body of an abstraction is
+    // normalized once with its variable named by its level, applying the
+    // result only substitutes for that variable
+    pub fn normalize(term : Self) -> Self {
+        Hoas::normalize_at(term, 0)
+    }
+
+    fn normalize_at(term : Self, level : usize) -> Self {
+        use Hoas::*;
+        match Hoas::whnf(term) {
+            Var(x) => Var(x),
             Abs(body) => {
-                Hoas::lam(move |x| Hoas::step(body.apply(x)))
+                let body = Hoas::normalize_at(body.apply(Var(level as isize)), level + 1);
+                Hoas::lam(move |x| body.substitute(level as isize, &x))
             },
-            App(f, arg) => match Hoas::step(*f) {
-                Abs(body) => body.apply(*arg),
-                t => Hoas::app(t, Hoas::step(*arg))
+            App(f, arg) => Hoas::app(
+                Hoas::normalize_at(*f, level),
+                Hoas::normalize_at(*arg, level))
+        }
+    }
+
+    fn substitute(&self, var : isize, term : &Hoas) -> Self {
+        use Hoas::*;
+        match self {
+            Var(x) if *x == var => term.clone(),
+            Var(x) => Var(*x),
+            Abs(body) => {
+                let (body, term) = (body.clone(), term.clone());
+                Hoas::lam(move |x| body.apply(x).substitute(var, &term))
+            },
+            App(f, arg) => Hoas::app(f.substitute(var, term), arg.substitute(var, term))
+        }
+    }
+
+    // Read back to a named tree, abstractions are instantiated with a
+    // variable named by their level
+    pub fn to_tree(&self) -> Tree {
+        fn helper(term : &Hoas, level : usize) -> Tree {
+            use Hoas::*;
+            match term {
+                Var(x) => Tree::Var(*x as usize),
+                Abs(body) => {
+                    let body = body.apply(Var(level as isize));
+                    Tree::Abs(level, Box::new(helper(&body, level + 1)))
+                },
+                App(f, arg) => Tree::App(
+                    Box::new(helper(f, level)),
+                    Box::new(helper(arg, level)))
             }
         }
+        helper(self, 0)
     }
 
-    fn convert(tree : Tree, map : Rc<RefCell<HashMap<usize, Hoas>>>) -> Hoas {
-        match tree {
-            Tree::Var(x) => {
-                if let Some(term) = map.borrow().get(&x) {
-                    term.clone()
-                } else {
-                    Hoas::Var(x as isize)
-                }
+    fn convert(code : &Rc<Code>, scope : &Rc<Scope>) -> Hoas {
+        match &**code {
+            Code::Var(x) => {
+                Scope::lookup(scope, *x).unwrap_or(Hoas::Var(*x as isize))
             },
-            Tree::Abs(id, body) => {
+            Code::Abs(id, body) => {
+                let (id, body, scope) = (*id, body.clone(), scope.clone());
                 Hoas::lam(move |x| {
-                    map.borrow_mut().insert(id, x.clone());
-                    Hoas::convert(*body, map)
+                    let scope = Rc::new(Scope::Bind(id, x, scope));
+                    Hoas::convert(&body, &scope)
                 })
             },
-            Tree::App(f, arg) => {
+            Code::App(f, arg) => {
                 Hoas::app(
-                    Hoas::convert(*f, map.clone()),
-                    Hoas::convert(*arg, map))
+                    Hoas::convert(f, scope),
+                    Hoas::convert(arg, scope))
             }
         }
     }
@@ -111,34 +180,12 @@ impl Display for Hoas {
 
 impl Strategy for Hoas {
     fn build(&mut self, tree : &Tree) {
-        let map = Rc::new(RefCell::new(HashMap::new()));
-        let term = Hoas::convert(tree.clone(), map);
-        *self = term;
+        *self = Hoas::convert(&Code::from(tree), &Rc::new(Scope::Nil));
     }
 
     fn reduce(&mut self) -> Option<u64> {
-        use Hoas::*;
-        *self = Hoas::step(self.clone());
-        // FIXME: Somehow printing modifies self?
-        //println!("{}", self);
-        let mut result = None;
-        if let Abs(body) = self {
-            let next = body.apply(Var(-1));
-            if let Abs(body) = next {
-                let mut next = body.apply(Var(-1));
-                let mut counter = 0i64;
-                loop {
-                    if let App(ref left, ref right) = next {
-                        if let Var(ref i) = **left {
-                            counter += *i as i64;
-                        } else { break; }
-                        next = *right.clone();
-                    } else { break; }
-                }
-                result = Some((-counter) as u64);
-            }
-        }
-        result
+        *self = Hoas::normalize(self.clone());
+        self.to_tree().convert()
     }
 
     fn name(&self) -> String {
@@ -0,0 +1,130 @@
+use std::rc::Rc;
+
+use crate::tree::Tree;
+use crate::strategy::Strategy;
+use crate::trace;
+use crate::value;
+
+// Krivine's machine over de Bruijn indices with a linked list environment.
+// The machine itself only reaches weak head normal form, the strong variant
+// reads back the result by continuing under binders with the bound variable
+// instantiated to its level.
+
+#[derive(Clone)]
+enum Closure<'a> {
+    Code(&'a Tree, Rc<Env<'a>>),
+    Level(usize)
+}
+
+type Env<'a> = value::Env<Closure<'a>>;
+
+enum Whnf<'a> {
+    Abs(&'a Tree, Rc<Env<'a>>),
+    Neutral(usize, Vec<Closure<'a>>)
+}
+
+#[derive(Debug)]
+pub struct Krivine {
+    tree : Tree,
+    strong : bool
+}
+
+impl Krivine {
+    pub fn new() -> Krivine {
+        Krivine { tree: Tree::Var(0), strong: true }
+    }
+
+    pub fn weak() -> Krivine {
+        Krivine { tree: Tree::Var(0), strong: false }
+    }
+
+    fn run<'a>(tree : &'a Tree, env : Rc<Env<'a>>) -> Whnf<'a> {
+        let mut stack = vec![];
+        let (mut tree, mut env) = (tree, env);
+        loop {
+            if trace::enabled() {
+                let rule = match tree {
+                    Tree::App(_, _) => "push",
+                    Tree::Abs(_, _) if stack.is_empty() => "halt",
+                    Tree::Abs(_, _) => "grab",
+                    Tree::Var(_) => "access"
+                };
+                trace::record(rule, Env::len(&env), stack.len(), || tree.to_indexed_string());
+            }
+            match tree {
+                Tree::App(left, right) => {
+                    stack.push(Closure::Code(right, env.clone()));
+                    tree = left;
+                },
+                Tree::Abs(_, body) => {
+                    if let Some(closure) = stack.pop() {
+                        env = Rc::new(Env::Cons(closure, env));
+                        tree = body;
+                    } else {
+                        return Whnf::Abs(body, env);
+                    }
+                },
+                Tree::Var(index) => {
+                    match Env::lookup(&env, *index) {
+                        Closure::Code(t, e) => {
+                            tree = t;
+                            env = e;
+                        },
+                        Closure::Level(level) => {
+                            stack.reverse();
+                            return Whnf::Neutral(level, stack);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn readback(whnf : Whnf, level : usize) -> Tree {
+        match whnf {
+            Whnf::Abs(body, env) => {
+                let env = Rc::new(Env::Cons(Closure::Level(level), env));
+                let body = Krivine::run(body, env);
+                Tree::Abs(level, Box::new(Krivine::readback(body, level + 1)))
+            },
+            Whnf::Neutral(x, args) => {
+                let mut result = Tree::Var(x);
+                for arg in args {
+                    let arg = match arg {
+                        Closure::Code(t, e) => Krivine::readback(Krivine::run(t, e), level),
+                        Closure::Level(x) => Tree::Var(x)
+                    };
+                    result = Tree::App(Box::new(result), Box::new(arg));
+                }
+                result
+            }
+        }
+    }
+}
+
+impl Strategy for Krivine {
+    fn build(&mut self, tree : &Tree) {
+        self.tree = Tree::fix_indices(tree.clone());
+    }
+
+    fn reduce(&mut self) -> Option<u64> {
+        let whnf = Krivine::run(&self.tree, Rc::new(Env::Nil));
+        if self.strong {
+            Krivine::readback(whnf, 0).convert()
+        } else {
+            None
+        }
+    }
+
+    fn normalizes(&self) -> bool {
+        self.strong
+    }
+
+    fn name(&self) -> String {
+        if self.strong {
+            String::from("krivine machine")
+        } else {
+            String::from("krivine machine (whnf)")
+        }
+    }
+}
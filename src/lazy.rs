@@ -0,0 +1,188 @@
+use std::rc::Rc;
+use std::mem;
+
+use crate::tree::Tree;
+use crate::strategy::Strategy;
+use crate::trace;
+use crate::value;
+
+// A call-by-need machine in the style of Sestoft's "Deriving a Lazy Abstract
+// Machine". Arguments are allocated as thunks on a heap and overwritten with
+// their value the first time they are forced, so every argument is evaluated
+// at most once. Readback instantiates binders with neutral heap cells that
+// collect the addresses of the thunks they are applied to.
+
+type Env = value::Env<usize>;
+
+enum Cell<'a> {
+    Thunk(&'a Tree, Rc<Env>),
+    Value(&'a Tree, Rc<Env>),
+    Neutral(usize, Vec<usize>),
+    Blackhole
+}
+
+enum Frame {
+    Argument(usize),
+    Update(usize)
+}
+
+enum Control<'a> {
+    Eval(&'a Tree, Rc<Env>),
+    Neutral(usize, Vec<usize>)
+}
+
+struct Heap<'a> {
+    cells : Vec<Cell<'a>>
+}
+
+impl<'a> Heap<'a> {
+    fn alloc(&mut self, cell : Cell<'a>) -> usize {
+        self.cells.push(cell);
+        self.cells.len() - 1
+    }
+
+    fn enter(&mut self, address : usize, stack : &mut Vec<Frame>) -> Control<'a> {
+        match mem::replace(&mut self.cells[address], Cell::Blackhole) {
+            Cell::Thunk(tree, env) => {
+                stack.push(Frame::Update(address));
+                Control::Eval(tree, env)
+            },
+            Cell::Value(tree, env) => {
+                self.cells[address] = Cell::Value(tree, env.clone());
+                Control::Eval(tree, env)
+            },
+            Cell::Neutral(level, args) => {
+                self.cells[address] = Cell::Neutral(level, args.clone());
+                Control::Neutral(level, args)
+            },
+            Cell::Blackhole => panic!("Term does not have a normal form.")
+        }
+    }
+
+    fn run(&mut self, control : Control<'a>, mut stack : Vec<Frame>) -> Control<'a> {
+        let mut control = control;
+        loop {
+            if trace::enabled() {
+                Heap::trace(&control, &stack);
+            }
+            control = match control {
+                Control::Eval(tree, env) => match tree {
+                    Tree::App(left, right) => {
+                        let address = if let Tree::Var(index) = **right {
+                            Env::lookup(&env, index)
+                        } else {
+                            self.alloc(Cell::Thunk(right, env.clone()))
+                        };
+                        stack.push(Frame::Argument(address));
+                        Control::Eval(left, env)
+                    },
+                    Tree::Var(index) => {
+                        let address = Env::lookup(&env, *index);
+                        self.enter(address, &mut stack)
+                    },
+                    Tree::Abs(_, body) => match stack.pop() {
+                        Some(Frame::Argument(address)) => {
+                            Control::Eval(body, Rc::new(Env::Cons(address, env)))
+                        },
+                        Some(Frame::Update(address)) => {
+                            self.cells[address] = Cell::Value(tree, env.clone());
+                            Control::Eval(tree, env)
+                        },
+                        None => return Control::Eval(tree, env)
+                    }
+                },
+                Control::Neutral(level, mut args) => match stack.pop() {
+                    Some(Frame::Argument(address)) => {
+                        args.push(address);
+                        Control::Neutral(level, args)
+                    },
+                    Some(Frame::Update(address)) => {
+                        self.cells[address] = Cell::Neutral(level, args.clone());
+                        Control::Neutral(level, args)
+                    },
+                    None => return Control::Neutral(level, args)
+                }
+            };
+        }
+    }
+
+    fn trace(control : &Control, stack : &[Frame]) {
+        match control {
+            Control::Eval(tree, env) => {
+                let rule = match (tree, stack.last()) {
+                    (Tree::App(_, _), _) => "app",
+                    (Tree::Var(_), _) => "var",
+                    (Tree::Abs(_, _), Some(Frame::Argument(_))) => "beta",
+                    (Tree::Abs(_, _), Some(Frame::Update(_))) => "update",
+                    (Tree::Abs(_, _), None) => "halt"
+                };
+                trace::record(rule, Env::len(env), stack.len(), || tree.to_indexed_string());
+            },
+            Control::Neutral(level, args) => {
+                let rule = match stack.last() {
+                    Some(Frame::Argument(_)) => "neutral",
+                    Some(Frame::Update(_)) => "update",
+                    None => "halt"
+                };
+                trace::record(rule, 0, stack.len(), || format!("x{} applied to {} thunks", level, args.len()));
+            }
+        }
+    }
+
+    fn force(&mut self, address : usize) -> Control<'a> {
+        let mut stack = vec![];
+        let control = self.enter(address, &mut stack);
+        self.run(control, stack)
+    }
+
+    // Continue under binders by instantiating them with neutral cells,
+    // binders are named by their level
+    fn readback(&mut self, control : Control<'a>, level : usize) -> Tree {
+        match control {
+            Control::Eval(Tree::Abs(_, body), env) => {
+                let fresh = self.alloc(Cell::Neutral(level, vec![]));
+                let env = Rc::new(Env::Cons(fresh, env));
+                let control = self.run(Control::Eval(body, env), vec![]);
+                Tree::Abs(level, Box::new(self.readback(control, level + 1)))
+            },
+            Control::Neutral(x, args) => {
+                let mut result = Tree::Var(x);
+                for address in args {
+                    let control = self.force(address);
+                    let arg = self.readback(control, level);
+                    result = Tree::App(Box::new(result), Box::new(arg));
+                }
+                result
+            },
+            Control::Eval(_, _) => panic!("Impossible machine state.")
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Lazy {
+    tree : Tree
+}
+
+impl Lazy {
+    pub fn new() -> Lazy {
+        Lazy { tree: Tree::Var(0) }
+    }
+}
+
+impl Strategy for Lazy {
+    fn build(&mut self, tree : &Tree) {
+        self.tree = Tree::fix_indices(tree.clone());
+    }
+
+    fn reduce(&mut self) -> Option<u64> {
+        let mut heap = Heap { cells: vec![] };
+        let control = Control::Eval(&self.tree, Rc::new(Env::Nil));
+        let control = heap.run(control, vec![]);
+        heap.readback(control, 0).convert()
+    }
+
+    fn name(&self) -> String {
+        String::from("call-by-need machine")
+    }
+}
@@ -1,4 +1,8 @@
 use std::time::Instant;
+use std::env;
+use std::path::Path;
+use std::process;
+use std::fs;
 
 mod tree;
 mod expr;
@@ -8,6 +12,19 @@ mod cek;
 mod hoas;
 mod optimal;
 mod dag;
+mod explicit;
+mod nbe;
+mod lazy;
+mod krivine;
+mod cbv;
+mod secd;
+mod trace;
+mod bytecode;
+mod closure;
+mod value;
+mod regress;
+mod arena;
+mod snapshot;
 
 use crate::tree::Tree;
 use crate::expr::{ListFold, ArithExpr};
@@ -15,9 +32,18 @@ use crate::strategy::Strategy;
 use crate::cek::Machine;
 use crate::hoas::Hoas;
 use crate::optimal::Net;
-use crate::dag::Dag;
+use crate::dag::{Dag, Order};
+use crate::explicit::Sigma;
+use crate::nbe::Nbe;
+use crate::lazy::Lazy;
+use crate::krivine::Krivine;
+use crate::cbv::Cbv;
+use crate::secd::Secd;
+use crate::trace::Trace;
+use crate::bytecode::Vm;
+use crate::closure::Compiled;
 
-fn benchmark(strategies : &mut Vec<Box<Strategy>>, depth : usize, len : usize) {
+fn benchmark(strategies : &mut Vec<Box<dyn Strategy>>, depth : usize, len : usize) {
     let mut averages : Vec<_> = strategies.iter().map(|_| 0.0).collect();
     let (sample, measure) = (3, 3);
     for _ in 0..sample {
@@ -32,7 +58,9 @@ fn benchmark(strategies : &mut Vec<Box<Strategy>>, depth : usize, len : usize) {
             let ref mut strategy = strategies[i];
             strategy.build(&tree);
             let output = strategy.reduce();
-            //assert_eq!(value, output.expect("Invalid u64 value."));
+            if strategy.normalizes() {
+                assert_eq!(Some(value), output, "{} disagrees with ListFold::eval.", strategy.name());
+            }
             let now = Instant::now();
             for _ in 0..measure {
                 strategy.build(&tree);
@@ -46,17 +74,40 @@ fn benchmark(strategies : &mut Vec<Box<Strategy>>, depth : usize, len : usize) {
     println!("len: {}, depth: {}", len, depth);
     for i in 0..strategies.len() {
         averages[i] /= sample as f64;
-        println!("{}: {}ms", strategies[i].name(), averages[i] * 1000.0);
+        let mut line = format!("{}: {}ms", strategies[i].name(), averages[i] * 1000.0);
+        if let Some(steps) = strategies[i].steps() {
+            line.push_str(&format!(", {} steps", steps));
+        }
+        if let Some(nodes) = strategies[i].nodes() {
+            line.push_str(&format!(", {} nodes", nodes));
+        }
+        println!("{}", line);
     }
 }
 
+fn strategies() -> Vec<Box<dyn Strategy>> {
+    vec![
+        Box::new(Tree::Var(0)),
+        Box::new(Nbe::new()),
+        Box::new(Machine::new()),
+        Box::new(Cbv::new()),
+        Box::new(Secd::new()),
+        Box::new(Lazy::new()),
+        Box::new(Krivine::weak()),
+        Box::new(Krivine::new()),
+        Box::new(Vm::new()),
+        Box::new(Hoas::new()),
+        Box::new(Compiled::new()),
+        Box::new(Sigma::new()),
+        Box::new(Net::new()),
+        Box::new(Dag::new()),
+        Box::new(Dag::with_order(Order::Innermost)),
+        Box::new(Dag::hash_consed())
+    ]
+}
+
 fn helper() {
-    let mut strategies : Vec<Box<Strategy>> = vec![];
-    strategies.push(Box::new(Tree::Var(0)));
-    strategies.push(Box::new(Machine::new()));
-    strategies.push(Box::new(Hoas::new()));
-    //strategies.push(Box::new(Net::new()));
-    //strategies.push(Box::new(Dag::new()));
+    let mut strategies = strategies();
     benchmark(&mut strategies, 1, 1);
     benchmark(&mut strategies, 1, 2);
     benchmark(&mut strategies, 1, 5);
@@ -68,6 +119,149 @@ fn helper() {
     benchmark(&mut strategies, 2, 10);
 }
 
+// Reduce one generated workload with every strategy, writing the trace of
+// each machine that records one to `<dir>/<name>.trace`
+fn trace(depth : usize, len : usize, dir : &Path) {
+    if let Err(e) = fs::create_dir_all(dir) {
+        println!("failed to create {}: {}", dir.display(), e);
+        return;
+    }
+    let mut id = 0;
+    let expr = ListFold::gen(depth, len);
+    let tree = expr.elab(&mut id);
+    println!("result: {}", expr.eval());
+    for mut strategy in strategies() {
+        strategy.build(&tree);
+        trace::start();
+        let output = strategy.reduce();
+        let trace = trace::stop();
+        if trace.is_empty() { continue; }
+        let path = dir.join(format!("{}.trace", strategy.name().replace(' ', "-")));
+        match trace.write(&path) {
+            Ok(()) => println!("{}: {:?}, trace written to {}", strategy.name(), output, path.display()),
+            Err(e) => println!("{}: failed to write {}: {}", strategy.name(), path.display(), e)
+        }
+    }
+}
+
+// Write Graphviz renderings of one generated workload as a syntax tree, a
+// dag and an interaction net to `<dir>/{tree,dag,net}.dot`
+fn dot(depth : usize, len : usize, dir : &Path) {
+    if let Err(e) = fs::create_dir_all(dir) {
+        println!("failed to create {}: {}", dir.display(), e);
+        return;
+    }
+    let mut id = 0;
+    let tree = ListFold::gen(depth, len).elab(&mut id);
+    let graphs = vec![
+        ("tree", tree.to_dot()),
+        ("dag", Dag::from(&tree).to_dot()),
+        ("net", Net::from_tree(&tree).to_dot())
+    ];
+    for (name, graph) in graphs {
+        let path = dir.join(format!("{}.dot", name));
+        match fs::write(&path, graph) {
+            Ok(()) => println!("{} written to {}", name, path.display()),
+            Err(e) => println!("failed to write {}: {}", path.display(), e)
+        }
+    }
+}
+
+// Reduce one generated workload with every strategy, writing a DOT snapshot
+// of the graph after every step of each graph strategy to `<dir>/<name>/`
+fn snapshots(depth : usize, len : usize, dir : &Path) {
+    let mut id = 0;
+    let expr = ListFold::gen(depth, len);
+    let tree = expr.elab(&mut id);
+    println!("result: {}", expr.eval());
+    for mut strategy in strategies() {
+        let path = dir.join(strategy.name().replace(' ', "-"));
+        strategy.build(&tree);
+        snapshot::start(&path);
+        let output = strategy.reduce();
+        match snapshot::stop() {
+            Ok(0) => { },
+            Ok(count) => println!("{}: {:?}, {} snapshots written to {}",
+                strategy.name(), output, count, path.display()),
+            Err(e) => println!("{}: failed to write snapshots to {}: {}",
+                strategy.name(), path.display(), e)
+        }
+    }
+}
+
+fn replay(path : &Path, stop : Option<usize>) {
+    match Trace::read(path) {
+        Ok(trace) => trace.replay(stop),
+        Err(e) => println!("failed to read {}: {}", path.display(), e)
+    }
+}
+
+fn verify() {
+    let mut strategies = strategies();
+    let failures = regress::run(&mut strategies, &regress::cases())
+        + optimal::check_rules();
+    if failures > 0 {
+        println!("{} failures", failures);
+        process::exit(1);
+    }
+    println!("all cases passed");
+}
+
+fn usage() {
+    println!("usage: reducto");
+    println!("       reducto verify");
+    println!("       reducto trace <depth> <len> [dir]");
+    println!("       reducto replay <file> [step]");
+    println!("       reducto dot <depth> <len> [dir]");
+    println!("       reducto snapshot <depth> <len> [dir]");
+}
+
 fn main() {
-    helper();
+    let args : Vec<String> = env::args().skip(1).collect();
+    let args : Vec<&str> = args.iter().map(|x| x.as_str()).collect();
+    match args.as_slice() {
+        [] => helper(),
+        ["verify"] => verify(),
+        ["trace", depth, len, rest @ ..] if rest.len() <= 1 => {
+            match (depth.parse(), len.parse()) {
+                (Ok(depth), Ok(len)) => trace(depth, len, Path::new(rest.first().unwrap_or(&"."))),
+                _ => usage()
+            }
+        },
+        ["dot", depth, len, rest @ ..] if rest.len() <= 1 => {
+            match (depth.parse(), len.parse()) {
+                (Ok(depth), Ok(len)) => dot(depth, len, Path::new(rest.first().unwrap_or(&"."))),
+                _ => usage()
+            }
+        },
+        ["snapshot", depth, len, rest @ ..] if rest.len() <= 1 => {
+            match (depth.parse(), len.parse()) {
+                (Ok(depth), Ok(len)) => snapshots(depth, len, Path::new(rest.first().unwrap_or(&"."))),
+                _ => usage()
+            }
+        },
+        ["replay", file] => replay(Path::new(file), None),
+        ["replay", file, step] => {
+            match step.parse() {
+                Ok(step) => replay(Path::new(file), Some(step)),
+                Err(_) => usage()
+            }
+        },
+        _ => usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regressions() {
+        assert_eq!(regress::run(&mut strategies(), &regress::cases()), 0);
+    }
+
+    #[test]
+    fn interaction_rules() {
+        assert_eq!(optimal::check_rules(), 0);
+    }
 }
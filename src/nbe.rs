@@ -0,0 +1,69 @@
+use std::rc::Rc;
+
+use crate::tree::Tree;
+use crate::strategy::Strategy;
+use crate::value::{self, Neutral};
+
+// Normalization by evaluation: de Bruijn terms are evaluated into a semantic
+// domain where abstractions are closures and stuck computations are neutral
+// terms over variable levels, normal forms are then read back by applying
+// closures to fresh levels.
+
+type Value<'a> = value::Value<&'a Tree>;
+type Env<'a> = value::Env<Value<'a>>;
+
+#[derive(Debug)]
+pub struct Nbe {
+    tree : Tree
+}
+
+impl Nbe {
+    pub fn new() -> Nbe {
+        Nbe { tree: Tree::Var(0) }
+    }
+
+    fn eval<'a>(tree : &'a Tree, env : &Rc<Env<'a>>) -> Value<'a> {
+        match tree {
+            Tree::Var(index) => Env::lookup(env, *index),
+            Tree::Abs(_, body) => Value::Closure(body, env.clone()),
+            Tree::App(left, right) => {
+                let left = Nbe::eval(left, env);
+                let right = Nbe::eval(right, env);
+                Nbe::apply(left, right)
+            }
+        }
+    }
+
+    fn apply<'a>(function : Value<'a>, argument : Value<'a>) -> Value<'a> {
+        match function {
+            Value::Closure(body, env) => {
+                let env = Rc::new(Env::Cons(argument, env));
+                Nbe::eval(body, &env)
+            },
+            Value::Neutral(neutral) => {
+                Value::Neutral(Rc::new(Neutral::App(neutral, argument)))
+            }
+        }
+    }
+
+    pub fn normalize(tree : &Tree) -> Tree {
+        let value = Nbe::eval(tree, &Rc::new(Env::Nil));
+        value::readback(value, 0, &mut |body, env, fresh| {
+            Nbe::eval(body, &Rc::new(Env::Cons(fresh, env)))
+        })
+    }
+}
+
+impl Strategy for Nbe {
+    fn build(&mut self, tree : &Tree) {
+        self.tree = Tree::fix_indices(tree.clone());
+    }
+
+    fn reduce(&mut self) -> Option<u64> {
+        Nbe::normalize(&self.tree).convert()
+    }
+
+    fn name(&self) -> String {
+        String::from("nbe")
+    }
+}
@@ -1,9 +1,12 @@
-use std::collections::{HashSet, HashMap, VecDeque};
+use std::collections::HashMap;
 use std::ops::{Index, IndexMut};
 use std::fmt;
+use std::mem;
 
+use crate::arena::{Arena, Id};
 use crate::tree::Tree;
 use crate::strategy::Strategy;
+use crate::snapshot;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 pub enum AgentKind {
@@ -20,62 +23,75 @@ fn order(a : AgentKind, b : AgentKind) -> (AgentKind, AgentKind) {
     (a.min(b), a.max(b))
 }
 
-#[derive(Eq, Hash, Clone)]
-pub struct Wire {
-    source : usize,
-    target : usize
+// Croissants, brackets and duplicators only move levels around and pass
+// through every agent they meet
+fn is_control(kind : AgentKind) -> bool {
+    kind == AgentKind::Bracket || kind == AgentKind::Croissant || kind == AgentKind::Duplicator
 }
 
-impl PartialEq for Wire {
-    fn eq(&self, other : &Wire) -> bool {
-        let (s1, t1) = (self.source, self.target);
-        let (s2, t2) = (other.source, other.target);
-        (s1 == s2 && t1 == t2) || (s1 == t2 && t1 == s2)
+// Change to the level of an agent a control agent of lower level passes through
+fn delta(kind : AgentKind) -> isize {
+    match kind {
+        AgentKind::Croissant => -1,
+        AgentKind::Bracket => 1,
+        _ => 0
     }
 }
 
-impl fmt::Debug for Wire {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}-{}", self.target, self.source)
-    }
+// The rewrite fired when two agents meet on their principal ports
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Rule {
+    // an application meets an abstraction of the same level
+    Beta,
+    // two control agents of the same kind and level cancel out
+    Annihilate,
+    // a control agent passes through the other agent and both are copied
+    Commute,
+    // an eraser consumes the other agent and spreads to its auxiliary ports
+    Erase,
+    // a croissant or bracket reaching the root is dropped
+    Discard,
+    // the pair does not interact
+    Stuck
 }
 
-impl Wire {
-    pub fn new(source : usize, target : usize) -> Wire {
-        Wire { source, target }
-    }
-
-    pub fn fill(&mut self, id : usize) {
-        if self.target == 0 {
-            self.target = id;
-        } else {
-            self.source = id;
+impl Rule {
+    fn name(self) -> &'static str {
+        match self {
+            Rule::Beta => "beta",
+            Rule::Annihilate => "annihilate",
+            Rule::Commute => "commute",
+            Rule::Erase => "erase",
+            Rule::Discard => "discard",
+            Rule::Stuck => "none"
         }
     }
+}
 
-    pub fn swap(&mut self) {
-        let temp = self.source;
-        self.source = self.target;
-        self.target = temp;
-    }
+type AgentId = Id<Agent>;
+
+// One end of a wire, an agent and the index of one of its ports. Port 0 is
+// the principal port.
+type Port = (AgentId, usize);
+
+fn dangling() -> Port {
+    (AgentId::null(), 0)
 }
 
 #[derive(Clone)]
 pub struct Agent {
     kind : AgentKind,
     level : isize,
-    wires : [usize; 3]
+    ports : [Port; 3]
 }
 
 impl Agent {
-    fn new(kind : AgentKind, level : isize, wires : Vec<usize>) -> Agent {
-        let mut result = Agent {
+    fn new(kind : AgentKind, level : isize) -> Agent {
+        Agent {
             kind,
             level,
-            wires: [0, 0, 0]
-        };
-        result.update(wires);
-        result
+            ports: [dangling(); 3]
+        }
     }
 
     fn len(&self) -> usize {
@@ -90,22 +106,6 @@ impl Agent {
             _ => 2
         }
     }
-
-    fn update(&mut self, wires : Vec<usize>) {
-        for i in 0..self.len() {
-            self[i] = wires[i];
-        }
-    }
-
-    fn port_of(&self, wire : usize) -> usize {
-        let mut result = 0;
-        for i in 0..self.len() {
-            if self.wires[i] == wire {
-                result = i;
-            }
-        }
-        result
-    }
 }
 
 impl fmt::Debug for Agent {
@@ -119,481 +119,553 @@ impl fmt::Debug for Agent {
             AgentKind::Duplicator => "D",
             AgentKind::Eraser => "e"
         };
-
-        match self.kind {
-            AgentKind::Root | AgentKind::Eraser
-                => write!(f, "{}[{}]", header, self[0]),
-            AgentKind::Bracket | AgentKind::Croissant
-                => write!(f, "{}[{}, {}]", header, self[0], self[1]),
-            _ => write!(f, "{}[{},{},{}]", header, self[0], self[1], self[2]),
-        }
+        let ports : Vec<_> = self.ports[..self.len()].iter()
+            .map(|(id, port)| format!("{}:{}", id, port))
+            .collect();
+        write!(f, "{}{}[{}]", header, self.level, ports.join(", "))
     }
 }
 
 impl Index<usize> for Agent {
-    type Output = usize;
+    type Output = Port;
 
-    fn index(&self, index : usize) -> &usize {
-        &self.wires[index]
+    fn index(&self, index : usize) -> &Port {
+        &self.ports[index]
     }
 }
 
 impl IndexMut<usize> for Agent {
-    fn index_mut(&mut self, index: usize) -> &mut usize {
-        &mut self.wires[index]
+    fn index_mut(&mut self, index: usize) -> &mut Port {
+        &mut self.ports[index]
+    }
+}
+
+// One level of a readback context. Entering a duplicator through an
+// auxiliary port pushes the port on its level, a bracket pairs its level with
+// the one above and a croissant opens a new empty level.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Level {
+    Empty,
+    Push(usize, Box<Level>),
+    Pair(Box<Level>, Box<Level>)
+}
+
+// The levels of a readback context, all levels past the end are empty
+#[derive(Clone, Default)]
+struct Context {
+    levels : Vec<Level>
+}
+
+impl Context {
+    fn take(&mut self, n : usize) -> Level {
+        match self.levels.get_mut(n) {
+            Some(level) => mem::replace(level, Level::Empty),
+            None => Level::Empty
+        }
+    }
+
+    fn set(&mut self, n : usize, level : Level) {
+        if self.levels.len() <= n {
+            self.levels.resize(n + 1, Level::Empty);
+        }
+        self.levels[n] = level;
+    }
+
+    fn insert(&mut self, n : usize, level : Level) {
+        if n <= self.levels.len() {
+            self.levels.insert(n, level);
+        } else {
+            self.set(n, level);
+        }
+    }
+
+    fn remove(&mut self, n : usize) -> Level {
+        if n < self.levels.len() {
+            self.levels.remove(n)
+        } else {
+            Level::Empty
+        }
+    }
+
+    fn prefix(&self, n : usize) -> Vec<Level> {
+        (0..n).map(|i| self.levels.get(i).cloned().unwrap_or(Level::Empty)).collect()
     }
 }
 
 #[derive(Debug)]
 pub struct Net {
-    agent_id : usize,
-    wire_id : usize,
-    agents : HashMap<usize, Agent>,
-    wires : HashMap<usize, Wire>
+    root : AgentId,
+    steps : usize,
+    peak : usize,
+    // agents whose principal port has been connected to another principal
+    // port, some of them may have been rewritten since
+    active : Vec<AgentId>,
+    agents : Arena<Agent>
 }
 
 impl Net {
     pub fn new() -> Net {
         Net {
-            agent_id: 1,
-            wire_id: 1,
-            agents: HashMap::new(),
-            wires: HashMap::new()
+            root: AgentId::null(),
+            steps: 0,
+            peak: 0,
+            active: vec![],
+            agents: Arena::new()
         }
     }
 
-    pub fn partner(&self, agent_id : usize, wire_id : usize) -> usize {
-        let wire = self.wire(wire_id);
-        if agent_id == wire.source {
-            wire.target
-        } else {
-            wire.source
-        }
+    pub fn peer(&self, port : Port) -> Port {
+        self.agent(port.0)[port.1]
     }
 
+    // Reads the normal form back by following paths from the root with the
+    // context of Gonthier, Abadi and Lévy. A path leaving a duplicator by its
+    // principal port takes the auxiliary port it entered the matching
+    // duplicator through, and a variable is bound by the innermost instance
+    // of its abstraction whose levels below its own agree with the path.
     pub fn to_tree(&self) -> Option<Tree> {
-        let mut map = HashMap::new();
-        let result = self.to_tree_helper(1, self.agent(1)[0], &mut map, 1000);
-        result.map(|x| Tree::fix_indices(x))
+        let mut binders = vec![];
+        self.to_tree_helper(self.agent(self.root)[0], Context::default(), &mut binders)
     }
 
-    pub fn to_tree_helper(&self, aid : usize, wid : usize, oracle : &mut HashMap<usize, usize>, gas : isize) -> Option<Tree> {
+    fn to_tree_helper(&self, mut port : Port, mut context : Context, binders : &mut Vec<(AgentId, Vec<Level>)>) -> Option<Tree> {
         use AgentKind::*;
-        if gas <= 0 { return None; }
-        let agent = self.agent(aid);
-        match agent.kind {
-            Root => {
-                let next_id = self.partner(aid, agent[0]);
-                self.to_tree_helper(next_id, agent[0], oracle, gas)
-            },
-            Bracket => {
-                let next_id = self.partner(aid, agent[1]);
-                self.to_tree_helper(next_id, agent[1], oracle, gas)
-            },
-            Croissant => {
-                let next_id = self.partner(aid, agent[1]);
-                self.to_tree_helper(next_id, agent[1], oracle, gas)
-            },
-            Application => {
-                let left_id = self.partner(aid, agent[0]);
-                let right_id = self.partner(aid, agent[2]);
-                let left = self.to_tree_helper(left_id, agent[0], oracle, gas - 1);
-                let right = self.to_tree_helper(right_id, agent[2], oracle, gas - 1);
-                if let Some(le) = left {
-                    if let Some(ri) = right {
-                        Some(Tree::App(
-                            Box::new(le),
-                            Box::new(ri)
-                        ))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            },
-            Lambda => {
-                let port = self.agent(aid).port_of(wid);
-                if port == 0 {
-                    let body_id = self.partner(aid, agent[1]);
-                    let body = self.to_tree_helper(body_id, agent[1], oracle, gas - 1);
-                    if let Some(b) = body {
-                        Some(Tree::Abs(aid, Box::new(b)))
-                    } else {
-                        None
+        loop {
+            let (aid, slot) = port;
+            let agent = self.agent(aid);
+            let level = agent.level as usize;
+            port = match (agent.kind, slot) {
+                (Lambda, 0) => {
+                    binders.push((aid, context.prefix(level)));
+                    let body = self.to_tree_helper(agent[1], context, binders);
+                    binders.pop();
+                    return Some(Tree::Abs(binders.len(), Box::new(body?)));
+                },
+                (Lambda, 2) => {
+                    let prefix = context.prefix(level);
+                    let position = binders.iter().rposition(|(id, levels)| *id == aid && *levels == prefix)?;
+                    return Some(Tree::Var(binders.len() - position - 1));
+                },
+                (Application, 1) => {
+                    let left = self.to_tree_helper(agent[0], context.clone(), binders)?;
+                    let right = self.to_tree_helper(agent[2], context, binders)?;
+                    return Some(Tree::App(Box::new(left), Box::new(right)));
+                },
+                (Duplicator, 0) => {
+                    match context.take(level) {
+                        Level::Push(slot, rest) => {
+                            context.set(level, *rest);
+                            agent[slot]
+                        },
+                        _ => return None
                     }
-                } else {
-                    Some(Tree::Var(aid))
-                }
-            },
-            Duplicator => {
-                let port = self.agent(aid).port_of(wid);
-                if port == 0 {
-                    if let Some(&p) = oracle.get(&aid) {
-                        let body_id = self.partner(aid, agent[p]);
-                        self.to_tree_helper(body_id, agent[p], oracle, gas - 1)
-                    } else {
-                        None
+                },
+                (Duplicator, _) => {
+                    let rest = context.take(level);
+                    context.set(level, Level::Push(slot, Box::new(rest)));
+                    agent[0]
+                },
+                (Croissant, 0) => {
+                    context.remove(level);
+                    agent[1]
+                },
+                (Croissant, _) => {
+                    context.insert(level, Level::Empty);
+                    agent[0]
+                },
+                (Bracket, 0) => {
+                    match context.take(level) {
+                        Level::Pair(low, high) => {
+                            context.set(level, *low);
+                            context.insert(level + 1, *high);
+                        },
+                        _ => return None
                     }
-                } else {
-                    let body_id = self.partner(aid, agent[0]);
-                    oracle.insert(aid, port);
-                    self.to_tree_helper(body_id, agent[0], oracle, gas - 1)
-                }
-            },
-            Eraser => {
-                None
+                    agent[1]
+                },
+                (Bracket, _) => {
+                    let low = context.remove(level);
+                    let high = context.take(level);
+                    context.set(level, Level::Pair(Box::new(low), Box::new(high)));
+                    agent[0]
+                },
+                // erased or malformed parts of the net are not part of the
+                // normal form
+                _ => return None
+            };
+        }
+    }
+
+    // Graphviz rendering, every agent is a table with its principal port on
+    // top and its auxiliary ports below. Principal ports are filled and
+    // wires joining two principal ports, the active pairs, are drawn in red.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph net {\n    node [shape=plaintext, fontname=\"monospace\"];\n");
+        for (id, agent) in self.agents.iter() {
+            let name = match agent.kind {
+                AgentKind::Application => "@",
+                AgentKind::Lambda => "λ",
+                AgentKind::Duplicator => "fan",
+                AgentKind::Croissant => "croissant",
+                AgentKind::Bracket => "bracket",
+                AgentKind::Eraser => "erase",
+                AgentKind::Root => "root"
+            };
+            let aux : Vec<_> = (1..agent.len())
+                .map(|i| format!("<td port=\"p{}\">{}</td>", i, i))
+                .collect();
+            let span = aux.len().max(1);
+            let mut label = format!(
+                "<table border=\"0\" cellborder=\"1\" cellspacing=\"0\">\
+                 <tr><td port=\"p0\" colspan=\"{}\" bgcolor=\"lightcoral\">0</td></tr>\
+                 <tr><td colspan=\"{}\">{} {}</td></tr>", span, span, name, agent.level);
+            if !aux.is_empty() {
+                label.push_str(&format!("<tr>{}</tr>", aux.concat()));
+            }
+            label.push_str("</table>");
+            out.push_str(&format!("    a{} [label=<{}>];\n", id, label));
+        }
+        for (id, agent) in self.agents.iter() {
+            for source in 0..agent.len() {
+                // every wire is seen from both of its ends
+                let (peer, target) = agent[source];
+                if peer.is_null() || (peer, target) < (id, source) { continue; }
+                let style = if source == 0 && target == 0 { ", color=red, penwidth=2" } else { "" };
+                out.push_str(&format!("    a{}:p{} -> a{}:p{} [dir=none{}];\n",
+                    id, source, peer, target, style));
             }
         }
+        out.push_str("}\n");
+        out
     }
 
     pub fn from_tree(tree : &Tree) -> Net {
         let mut net = Net::new();
-        let mut map = HashMap::new();
-        let root_id = net.add_agent(Agent::new(AgentKind::Root, 0, vec![0]));
-        let root_wire = net.add_wire(Wire::new(root_id, 0));
-        net.mut_agent(root_id)[0] = root_wire;
-        let remaining = net.from_tree_helper(tree, root_wire, 0, &mut map);
-        net.mut_wire(root_wire).target = remaining;
-        net.fix_dangling_lambdas();
+        let root_id = net.add_agent(Agent::new(AgentKind::Root, 0));
+        net.root = root_id;
+        let mut free = HashMap::new();
+        let top = net.translate(tree, 0, &mut free);
+        assert!(free.is_empty(), "Free variables are not supported.");
+        net.connect((root_id, 0), top);
         net
     }
 
-    fn fix_dangling_lambdas(&mut self) {
-        let mut agents = vec![];
-        for (key, val) in self.agents.iter() {
-            if val[2] == 0 && val.kind == AgentKind::Lambda {
-                agents.push(*key);
-            }
-        }
-
-        for id in agents {
-            let eid = self.add_agent(Agent::new(AgentKind::Eraser, 0, vec![0]));
-            let wire = self.add_wire(Wire::new(id, eid));
-            self.mut_agent(eid)[0] = wire;
-            self.mut_agent(id)[2] = wire;
-        }
-    }
-
-    fn from_tree_helper(&mut self, tree : &Tree, dangling : usize, level : isize, name_map : &mut HashMap<usize, usize>) -> usize {
+    // Builds the net of a term at the given level and returns the port of its
+    // root, leaving the ports towards the binders of its free variables in
+    // `free`. Every occurrence gets a croissant at its own level, arguments
+    // get a bracket at the level of their application on each of their free
+    // variables, and occurrences meeting in an application share the way to
+    // their binder through a duplicator at that level.
+    fn translate(&mut self, tree : &Tree, level : isize, free : &mut HashMap<usize, Port>) -> Port {
+        use AgentKind::*;
         match tree {
-            Tree::Var(id_isize) => {
-                let id = *id_isize as usize;
-                let lambda_id = *name_map.get(&id).expect("Free variables are not supported.");
-
-                let croissant_wire = self.add_wire(Wire::new(0, 0));
-                let croissant = self.add_agent(Agent::new(AgentKind::Croissant, level, vec![croissant_wire, dangling]));
-                self.mut_wire(croissant_wire).source = croissant;
-                let dangling = if level > 0 {
-                    let bracket_wire = self.add_wire(Wire::new(0, 0));
-                    let bracket = self.add_agent(Agent::new(AgentKind::Bracket, level - 1, vec![bracket_wire, croissant_wire]));
-                    self.mut_wire(bracket_wire).source = bracket;
-
-                    self.mut_wire(croissant_wire).target = bracket;
-                    let mut previous_wire = bracket_wire;
-                    for i in (0..(level - 1)).rev() {
-                        let temp_wire = self.add_wire(Wire::new(0, 0));
-                        let temp = self.add_agent(Agent::new(AgentKind::Bracket, i, vec![temp_wire, previous_wire]));
-                        self.mut_wire(temp_wire).source = temp;
-
-                        self.mut_wire(previous_wire).target = temp;
-                        previous_wire = temp_wire;
-                    }
-                    previous_wire
-                } else {
-                    croissant_wire
-                };
-
-                if self.agent(lambda_id)[2] == 0 {
-                    self.mut_agent(lambda_id)[2] = dangling;
-                    self.mut_wire(dangling).target = lambda_id;
-                } else {
-                    let previous_wire = self.agent(lambda_id)[2];
-                    let new_wire = self.add_wire(Wire::new(lambda_id, 0));
-                    let dup_id = self.add_agent(Agent::new(
-                        AgentKind::Duplicator,
-                        level,
-                        vec![new_wire, previous_wire, dangling])
-                    );
-                    self.mut_wire(new_wire).fill(dup_id);
-                    self.mut_agent(lambda_id)[2] = new_wire;
-                    if self.wire(previous_wire).target == lambda_id {
-                        self.mut_wire(previous_wire).target = dup_id;
-                    } else {
-                        self.mut_wire(previous_wire).source = dup_id;
-                    }
-                    self.mut_wire(dangling).target = dup_id;
-                }
-                croissant
+            Tree::Var(id) => {
+                let croissant_id = self.add_agent(Agent::new(Croissant, level));
+                free.insert(*id, (croissant_id, 0));
+                (croissant_id, 1)
             },
-            Tree::Abs(id_isize, body) => {
-                let id = *id_isize as usize;
-
-                let lambda_id = self.add_agent(Agent::new(AgentKind::Lambda, level, vec![0, 0, 0]));
-                let body_wire = self.add_wire(Wire::new(lambda_id, 0));
-                self.mut_agent(lambda_id).update(vec![dangling, body_wire, 0]);
-
-                name_map.insert(id, lambda_id);
-                let body_id = self.from_tree_helper(body, body_wire, level, name_map);
-                self.mut_wire(body_wire).fill(body_id);
-                lambda_id
+            Tree::Abs(id, body) => {
+                let lambda_id = self.add_agent(Agent::new(Lambda, level));
+                let body = self.translate(body, level, free);
+                self.connect((lambda_id, 1), body);
+                let var = match free.remove(id) {
+                    Some(var) => var,
+                    None => (self.add_agent(Agent::new(Eraser, 0)), 0)
+                };
+                self.connect((lambda_id, 2), var);
+                (lambda_id, 0)
             },
             Tree::App(left, right) => {
-                let application_id = self.add_agent(Agent::new(AgentKind::Application, level, vec![0, 0, 0]));
-                let left_wire = self.add_wire(Wire::new(application_id, 0));
-                let right_wire = self.add_wire(Wire::new(application_id, 0));
-                self.mut_agent(application_id).update(vec![left_wire, dangling, right_wire]);
-
-                let left_id = self.from_tree_helper(left, left_wire, level, name_map);
-                self.mut_wire(left_wire).fill(left_id);
-
-                let right_id = self.from_tree_helper(right, right_wire, level + 1, name_map);
-                self.mut_wire(right_wire).fill(right_id);
-                application_id
+                let application_id = self.add_agent(Agent::new(Application, level));
+                let left = self.translate(left, level, free);
+                self.connect((application_id, 0), left);
+                let mut args = HashMap::new();
+                let right = self.translate(right, level + 1, &mut args);
+                self.connect((application_id, 2), right);
+                for (id, var) in args {
+                    let bracket_id = self.add_agent(Agent::new(Bracket, level));
+                    self.connect((bracket_id, 1), var);
+                    let var = match free.remove(&id) {
+                        Some(other) => {
+                            let dup_id = self.add_agent(Agent::new(Duplicator, level));
+                            self.connect((dup_id, 1), other);
+                            self.connect((dup_id, 2), (bracket_id, 0));
+                            (dup_id, 0)
+                        },
+                        None => (bracket_id, 0)
+                    };
+                    free.insert(id, var);
+                }
+                (application_id, 1)
             }
         }
     }
 
-    fn add_agent(&mut self, agent : Agent) -> usize {
-        self.agents.insert(self.agent_id, agent);
-        self.agent_id += 1;
-        self.agent_id - 1
-    }
-
-    fn mut_agent(&mut self, id : usize) -> &mut Agent {
-        self.agents.get_mut(&id)
-            .expect(format!("fn mut_agent {} failed", id).as_str())
+    fn add_agent(&mut self, agent : Agent) -> AgentId {
+        self.agents.insert(agent)
     }
 
-    fn agent(&self, id : usize) -> &Agent {
-        self.agents.get(&id)
-            .expect(format!("fn agent {} failed", id).as_str())
+    fn mut_agent(&mut self, id : AgentId) -> &mut Agent {
+        self.agents.get_mut(id)
+            .unwrap_or_else(|| panic!("fn mut_agent {} failed", id))
     }
 
-    fn add_wire(&mut self, wire : Wire) -> usize {
-        self.wires.insert(self.wire_id, wire);
-        self.wire_id += 1;
-        self.wire_id - 1
+    fn agent(&self, id : AgentId) -> &Agent {
+        self.agents.get(id)
+            .unwrap_or_else(|| panic!("fn agent {} failed", id))
     }
 
-    fn mut_wire(&mut self, id : usize) -> &mut Wire {
-        self.wires.get_mut(&id)
-            .expect(format!("fn mut_wire {} failed", id).as_str())
+    // Every wire is made here, so every active pair of the net is seen here
+    // when it comes into being
+    pub fn connect(&mut self, a : Port, b : Port) {
+        self.mut_agent(a.0)[a.1] = b;
+        self.mut_agent(b.0)[b.1] = a;
+        if a.1 == 0 && b.1 == 0 {
+            self.active.push(a.0);
+        }
     }
 
-    fn wire(&self, id : usize) -> &Wire {
-        self.wires.get(&id)
-            .expect(format!("fn wire {} failed", id).as_str())
+    // Plugs the wire at `old` into `new` instead. The agent owning `old` may
+    // be about to be removed, wires between two such agents are followed on
+    // later calls, so loops inside an active pair come out right.
+    pub fn replace(&mut self, old : Port, new : Port) {
+        let other = self.peer(old);
+        self.connect(other, new);
     }
 
-    pub fn replace(&mut self,
-        port : usize,
-        wire_id : usize,
-        old_id : usize,
-        new_id : usize)
-    {
-        let wire = self.wires.get_mut(&wire_id)
-            .expect(format!("fn replace, wire_id {}, missing", wire_id).as_str());
-        let new = self.agents.get_mut(&new_id)
-            .expect(format!("fn replace, agent_id {}, missing", new_id).as_str());
-        if wire.source == old_id {
-            wire.source = new_id;
-            new[port] = wire_id;
-        } else {
-            wire.target = new_id;
-            new[port] = wire_id;
-        }
+    // Joins whatever the two ports are plugged into
+    fn join(&mut self, a : Port, b : Port) {
+        let other = self.peer(b);
+        self.replace(a, other);
     }
 
-    pub fn connect(&mut self,
-        dangling1_id : usize,
-        wire1_id : usize,
-        dangling2_id : usize,
-        wire2_id : usize)
-    {
-        let wire1 = self.wires.remove(&wire1_id)
-            .expect(format!("fn connect, wire_id {}, missing", wire1_id).as_str());
-        let wire2 = self.wires.remove(&wire2_id)
-            .expect(format!("fn connect, wire_id {}, missing", wire2_id).as_str());
-        let agent1_id = if wire1.source == dangling1_id
-            { wire1.target }
-            else { wire1.source };
-        let agent2_id = if wire2.source == dangling2_id
-            { wire2.target }
-            else { wire2.source };
-        let port1 = self.agent(agent1_id).port_of(wire1_id);
-        let port2 = self.agent(agent2_id).port_of(wire2_id);
-        let wire = Wire::new(agent1_id, agent2_id);
-        let id = self.add_wire(wire);
-        self.mut_agent(agent1_id)[port1] = id;
-        self.mut_agent(agent2_id)[port2] = id;
-    }
-
-    fn find_beta_pairs(&self) -> HashSet<usize> {
+    pub fn rule(&self, id : AgentId) -> Rule {
         use AgentKind::*;
-        let mut set = HashSet::new();
-        for (_, agent) in self.agents.iter() {
-            let incident = agent[0];
-            let wire = self.wire(incident);
-            let pair = order(self.agent(wire.source).kind,
-                self.agent(wire.target).kind);
-            match pair {
-                (Application, Lambda) => {
-                    set.insert(incident);
-                },
-                _ => { }
-            }
+        let agent = match self.agents.get(id) {
+            Some(agent) => agent,
+            None => return Rule::Stuck
+        };
+        let (pid, port) = agent[0];
+        if pid.is_null() || port != 0 { return Rule::Stuck; }
+        let partner = self.agent(pid);
+        let same = agent.level == partner.level;
+        match order(agent.kind, partner.kind) {
+            (Application, Lambda) if same => Rule::Beta,
+            (Bracket, Bracket) | (Croissant, Croissant) | (Duplicator, Duplicator) if same => Rule::Annihilate,
+            (Bracket, Root) | (Croissant, Root) => Rule::Discard,
+            (_, Root) => Rule::Stuck,
+            (Eraser, _) | (_, Eraser) => Rule::Erase,
+            (a, b) if is_control(a) || is_control(b) => Rule::Commute,
+            _ => Rule::Stuck
         }
-        set
     }
 
-    pub fn reduction_step(&mut self, id : usize) -> bool {
-        use AgentKind::*;
-
-        let mut result = true;
-        let (incident, wid) = {
-            let agent = self.agents.get(&id);
-            if agent.is_none() { return false; }
-            let agent = agent.expect("impossible");
-            let wire = self.wires.remove(&agent[0]);
-            if wire.is_none() { return false; }
-            let wire = wire.expect("impossible");
-            (wire, agent[0])
-        };
-        let (agent, aid, partner, pid) = {
-            let source = self.agents.remove(&incident.source);
-            let target = self.agents.remove(&incident.target);
-            if source.is_none() || target.is_none() { return false; }
-            let source = source.expect("impossible");
-            let target = target.expect("impossible");
-            if source.kind <= target.kind {
-                (source, incident.source, target, incident.target)
-            } else {
-                (target, incident.target, source, incident.source)
-            }
-        };
-        let kinds = (agent.kind, partner.kind);
-        let level = agent.level == partner.level;
-        
-        match (kinds.0, kinds.1, level) {
-            (_, Eraser, true) | (Eraser, _, true) => {
-                // Determine who is erasing who
-                let (_eraser, _eid, partner, pid) = {
-                    if agent.kind == AgentKind::Eraser {
-                        (agent, aid, partner, pid)
-                    } else {
-                        (partner, pid, agent, aid)
-                    }
-                };
+    fn is_normal(&self) -> bool {
+        self.agents.iter().all(|(id, _)| self.rule(id) == Rule::Stuck)
+    }
 
-                if partner.len() > 1 {
-                    let eraser_one = Agent::new(AgentKind::Eraser, 0, vec![0]);
-                    let eraser_two = eraser_one.clone();
-                    let e1id = self.add_agent(eraser_one);
-                    let e2id = self.add_agent(eraser_two);
-                    self.replace(0, partner[1], pid, e1id);
-                    self.replace(0, partner[2], pid, e2id);
+    pub fn reduction_step(&mut self, id : AgentId) -> bool {
+        let rule = self.rule(id);
+        if rule == Rule::Stuck { return false; }
+        let pid = self.agent(id)[0].0;
+        let (agent, partner) = (self.agent(id).clone(), self.agent(pid).clone());
+        match rule {
+            Rule::Beta | Rule::Annihilate => {
+                // the ports of an application and an abstraction line up
+                // as result with body and argument with variable
+                for i in 1..agent.len() {
+                    self.join((id, i), (pid, i));
                 }
             },
-            (Application, Lambda, true) | (Duplicator, Duplicator, true) => {
-                if agent[1] == agent[2] {
-                    self.wires.remove(&agent[1]);
-                    self.connect(pid, partner[1], pid, partner[2]);
-                } else if partner[1] == partner[2] {
-                    self.wires.remove(&partner[1]);
-                    self.connect(aid, agent[1], aid, agent[2]);
-                } else if agent[1] == partner[1] {
-                    self.wires.remove(&agent[1]);
-                    self.connect(aid, agent[2], pid, partner[2]);
-                } else if agent[2] == partner[2] {
-                    self.wires.remove(&agent[2]);
-                    self.connect(aid, agent[1], pid, partner[1]);
+            Rule::Erase => {
+                let (other, len) = if agent.kind == AgentKind::Eraser {
+                    (pid, partner.len())
                 } else {
-                    self.connect(aid, agent[1], pid, partner[1]);
-                    self.connect(aid, agent[2], pid, partner[2]);
+                    (id, agent.len())
+                };
+                for i in 1..len {
+                    let eid = self.add_agent(Agent::new(AgentKind::Eraser, 0));
+                    self.replace((other, i), (eid, 0));
                 }
             },
-            (Croissant, Croissant, true) | (Bracket, Bracket, true) => {
-                self.connect(aid, agent[1], pid, partner[1]);
-            }
-            (Duplicator, Lambda, false)
-            | (Application, Duplicator, false)
-            | (Duplicator, Duplicator, false) => {
-                let agent1_id = self.add_agent(agent.clone());
-                let agent2_id = self.add_agent(agent.clone());
-                let partner1_id = self.add_agent(partner.clone());
-                let partner2_id = self.add_agent(partner.clone());
-
-                let wire1x2 = self.add_wire(Wire::new(agent1_id, partner2_id));
-                let wire2x1 = self.add_wire(Wire::new(agent2_id, partner1_id));
-                let wire1x1 = self.add_wire(Wire::new(agent1_id, partner1_id));
-                let wire2x2 = self.add_wire(Wire::new(agent2_id, partner2_id));
-
-                self.mut_agent(agent1_id).update(vec![partner[1], wire1x1, wire1x2]);
-                self.mut_agent(agent2_id).update(vec![partner[2], wire2x1, wire2x2]);
-                self.mut_agent(partner1_id).update(vec![agent[1], wire1x1, wire2x1]);
-                self.mut_agent(partner2_id).update(vec![agent[2], wire1x2, wire2x2]);
-
-                self.replace(0, partner[1], pid, agent1_id);
-                self.replace(0, partner[2], pid, agent2_id);
-                self.replace(0, agent[1], aid, partner1_id);
-                self.replace(0, agent[2], aid, partner2_id);
+            Rule::Discard => {
+                let (control, root) = if agent.kind == AgentKind::Root { (pid, id) } else { (id, pid) };
+                self.replace((control, 1), (root, 0));
+                self.agents.remove(control);
             },
-            (Croissant, Lambda, false)
-            | (Application, Croissant, false)
-            | (Croissant, Duplicator, false)
-            | (Bracket, Lambda, false)
-            | (Application, Bracket, false)
-            | (Bracket, Duplicator, false) => {
-                let akind = agent.kind;
-                let (control, _cid, partner, pid) =
-                    if akind == Croissant || akind == Bracket {
-                        (agent, aid, partner, pid)
-                    } else {
-                        (partner, pid, agent, aid)
-                    };
-                let dlvl = if akind == Croissant {
-                        -1
-                    } else {
-                        1
-                    };
-                self.agents.insert(pid, partner.clone());
-                let control1_id = self.add_agent(control.clone());
-                let control2_id = self.add_agent(control.clone());
-                let old = vec![control[1], partner[1], partner[2]];
-                self.mut_agent(pid).update(vec![old[0], control1_id, control2_id]);
-                self.mut_agent(pid).level += dlvl;
-                self.mut_agent(control1_id).update(vec![partner[1], pid]);
-                self.mut_agent(control2_id).update(vec![partner[2], pid]);
+            Rule::Commute => {
+                // a copy of each agent goes to every auxiliary port of the
+                // other, the one with the higher level is moved by the other
+                let level = |a : &Agent, b : &Agent| {
+                    if a.level > b.level { a.level + delta(b.kind) } else { a.level }
+                };
+                let (agent_level, partner_level) = (level(&agent, &partner), level(&partner, &agent));
+                let agents : Vec<_> = (1..partner.len())
+                    .map(|_| self.add_agent(Agent::new(agent.kind, agent_level)))
+                    .collect();
+                let partners : Vec<_> = (1..agent.len())
+                    .map(|_| self.add_agent(Agent::new(partner.kind, partner_level)))
+                    .collect();
+                for (i, &copy) in agents.iter().enumerate() {
+                    self.replace((pid, i + 1), (copy, 0));
+                }
+                for (i, &copy) in partners.iter().enumerate() {
+                    self.replace((id, i + 1), (copy, 0));
+                }
+                for (i, &a) in agents.iter().enumerate() {
+                    for (j, &p) in partners.iter().enumerate() {
+                        self.connect((a, j + 1), (p, i + 1));
+                    }
+                }
             },
-            _ => {
-                // Undo what has been done
-                self.wires.insert(wid, incident);
-                self.agents.insert(aid, agent);
-                self.agents.insert(pid, partner);
-                result = false;
-            }
+            Rule::Stuck => unreachable!()
+        }
+        if rule != Rule::Discard {
+            self.agents.remove(id);
+            self.agents.remove(pid);
         }
-        result
+        self.steps += 1;
+        self.peak = self.peak.max(self.agents.len());
+        snapshot::record(rule.name(), Some(id.index()), || self.to_dot());
+        true
     }
 
     fn reduce(&mut self) {
-        let mut betas = self.find_beta_pairs();
-        let mut queue : VecDeque<_> = betas.drain().collect();
-        while let Some(id) = queue.pop_front() {
-            let agent = self.agent(id);
-            let mut branches = vec![];
-            for i in 0..agent.len() {
-                if i != 0 {
-                    let pid = self.partner(id, agent[i]);
-                    branches.push(pid);
+        self.steps = 0;
+        self.peak = self.agents.len();
+        snapshot::record("initial", None, || self.to_dot());
+        // entries for agents that have been rewritten or reused since are
+        // told apart by looking at the pair again
+        while let Some(id) = self.active.pop() {
+            self.reduction_step(id);
+        }
+        debug_assert!(self.is_normal(), "Active pairs left after reduction.");
+    }
+
+    // Every port of every agent is plugged into a live agent whose port
+    // points back
+    fn is_consistent(&self) -> bool {
+        self.agents.iter().all(|(id, agent)| {
+            (0..agent.len()).all(|i| {
+                let (peer, port) = agent[i];
+                match self.agents.get(peer) {
+                    Some(other) => port < other.len() && other[port] == (id, i),
+                    None => false
+                }
+            })
+        })
+    }
+}
+
+// A pair of agents facing each other as (kind, level), the rule they should
+// fire and, when they commute, the levels the copies of `x` and `y` end up at
+struct RuleCase {
+    x : (AgentKind, isize),
+    y : (AgentKind, isize),
+    rule : Rule,
+    copies : Option<(isize, isize)>
+}
+
+fn case(x : (AgentKind, isize), y : (AgentKind, isize), rule : Rule, copies : Option<(isize, isize)>) -> RuleCase {
+    RuleCase { x, y, rule, copies }
+}
+
+fn rule_cases() -> Vec<RuleCase> {
+    use AgentKind::*;
+    vec![
+        // erasers consume control agents whatever level they are at
+        case((Croissant, 1), (Eraser, 0), Rule::Erase, None),
+        case((Croissant, 1), (Eraser, 2), Rule::Erase, None),
+        case((Bracket, 1), (Eraser, 0), Rule::Erase, None),
+        case((Bracket, 1), (Eraser, 2), Rule::Erase, None),
+        case((Duplicator, 1), (Eraser, 2), Rule::Erase, None),
+        case((Eraser, 1), (Croissant, 0), Rule::Erase, None),
+        // croissants and brackets only move the one on the higher level
+        case((Croissant, 1), (Bracket, 1), Rule::Commute, Some((1, 1))),
+        case((Croissant, 1), (Bracket, 0), Rule::Commute, Some((2, 0))),
+        case((Croissant, 1), (Bracket, 2), Rule::Commute, Some((1, 1))),
+        case((Bracket, 1), (Croissant, 0), Rule::Commute, Some((0, 0))),
+        case((Bracket, 1), (Croissant, 2), Rule::Commute, Some((1, 3))),
+        // control agents of the same kind cancel on the same level only
+        case((Croissant, 1), (Croissant, 1), Rule::Annihilate, None),
+        case((Bracket, 1), (Bracket, 1), Rule::Annihilate, None),
+        case((Duplicator, 1), (Duplicator, 1), Rule::Annihilate, None),
+        case((Duplicator, 1), (Duplicator, 0), Rule::Commute, Some((1, 0))),
+        // only croissants and brackets leave through the root
+        case((Croissant, 1), (Root, 0), Rule::Discard, None),
+        case((Bracket, 1), (Root, 0), Rule::Discard, None),
+        case((Root, 0), (Croissant, 2), Rule::Discard, None),
+        case((Duplicator, 1), (Root, 0), Rule::Stuck, None),
+        // control agents pass through abstractions and applications
+        case((Croissant, 2), (Lambda, 1), Rule::Commute, Some((2, 1))),
+        case((Lambda, 2), (Croissant, 1), Rule::Commute, Some((1, 1))),
+        case((Application, 2), (Bracket, 1), Rule::Commute, Some((3, 1))),
+        case((Duplicator, 0), (Lambda, 1), Rule::Commute, Some((0, 1))),
+        case((Application, 1), (Lambda, 1), Rule::Beta, None),
+        case((Application, 1), (Lambda, 0), Rule::Stuck, None)
+    ]
+}
+
+// Puts two agents face to face with their auxiliary ports plugged into roots.
+// Returns the net, the first agent and every root, flagged if it is plugged
+// into the second agent and so receives copies of the first on commuting.
+fn face_to_face(x : (AgentKind, isize), y : (AgentKind, isize)) -> (Net, AgentId, Vec<(AgentId, bool)>) {
+    let mut net = Net::new();
+    let xid = net.add_agent(Agent::new(x.0, x.1));
+    let yid = net.add_agent(Agent::new(y.0, y.1));
+    net.connect((xid, 0), (yid, 0));
+    let mut roots = vec![];
+    for &id in [xid, yid].iter() {
+        for i in 1..net.agent(id).len() {
+            let root = net.add_agent(Agent::new(AgentKind::Root, 0));
+            net.connect((root, 0), (id, i));
+            roots.push((root, id == yid));
+        }
+    }
+    (net, xid, roots)
+}
+
+// Fires every case of `rule_cases` and checks the rule against it, along with
+// the kind and level of every copy left by commuting. Then fires every pair
+// of agent kinds one level below, on and above each other and checks that
+// the rewritten net is still wired up. Returns the number of failures.
+pub fn check_rules() -> usize {
+    use AgentKind::*;
+    let mut failures = 0;
+    for case in rule_cases() {
+        let (mut net, x, roots) = face_to_face(case.x, case.y);
+        let rule = net.rule(x);
+        net.reduction_step(x);
+        let copied = match case.copies {
+            Some((x_level, y_level)) => net.is_consistent() && roots.iter().all(|&(root, of_x)| {
+                let copy = net.agent(net.agent(root)[0].0);
+                let (kind, level) = if of_x { (case.x.0, x_level) } else { (case.y.0, y_level) };
+                copy.kind == kind && copy.level == level
+            }),
+            None => true
+        };
+        if rule != case.rule || !copied {
+            failures += 1;
+            println!("FAIL rule for {:?} and {:?}: {:?}, expected {:?}{}",
+                case.x, case.y, rule, case.rule, if copied { "" } else { ", copies at the wrong level" });
+        }
+    }
+    let kinds = [Application, Bracket, Croissant, Duplicator, Eraser, Lambda, Root];
+    for &a in kinds.iter() {
+        for &b in kinds.iter() {
+            for level in 0..3 {
+                let (mut net, x, roots) = face_to_face((a, 1), (b, level));
+                let rule = net.rule(x);
+                let fired = net.reduction_step(x);
+                let wired = net.is_consistent() && roots.iter().all(|&(root, _)| net.agents.get(root).is_some());
+                if fired != (rule != Rule::Stuck) || !wired {
+                    failures += 1;
+                    println!("FAIL {:?} for {:?} 1 and {:?} {}: net miswired", rule, a, b, level);
                 }
-            }
-            if self.reduction_step(id) {
-                queue.extend(branches.iter());
             }
         }
     }
+    failures
 }
 
 impl Strategy for Net {
@@ -610,4 +682,12 @@ impl Strategy for Net {
     fn name(&self) -> String {
         String::from("optimal")
     }
+
+    fn steps(&self) -> Option<usize> {
+        Some(self.steps)
+    }
+
+    fn nodes(&self) -> Option<usize> {
+        Some(self.peak)
+    }
 }
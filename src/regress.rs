@@ -0,0 +1,189 @@
+use crate::tree::Tree;
+use crate::expr::ArithExpr;
+use crate::strategy::Strategy;
+
+// Regression terms which some strategy has reduced to the wrong numeral in
+// the past, each paired with the numeral it should normalize to.
+
+pub struct Case {
+    pub name : &'static str,
+    pub tree : Tree,
+    pub value : u64
+}
+
+fn fresh(id : &mut usize) -> usize {
+    *id += 1;
+    *id
+}
+
+fn var(x : usize) -> Tree {
+    Tree::Var(x)
+}
+
+fn lam(x : usize, body : Tree) -> Tree {
+    Tree::Abs(x, Box::new(body))
+}
+
+fn app(left : Tree, right : Tree) -> Tree {
+    Tree::App(Box::new(left), Box::new(right))
+}
+
+fn numeral(n : u64, id : &mut usize) -> Tree {
+    ArithExpr::Numeral(n).elab(id)
+}
+
+// λm. λn. λf. λx. m f (n f x)
+fn add(id : &mut usize) -> Tree {
+    let (m, n, f, x) = (fresh(id), fresh(id), fresh(id), fresh(id));
+    lam(m, lam(n, lam(f, lam(x,
+        app(app(var(m), var(f)), app(app(var(n), var(f)), var(x)))))))
+}
+
+// λx. λy. x
+fn constant(id : &mut usize) -> Tree {
+    let (x, y) = (fresh(id), fresh(id));
+    lam(x, lam(y, var(x)))
+}
+
+// λn. λf. λx. f (n f x)
+fn succ(id : &mut usize) -> Tree {
+    let (n, f, x) = (fresh(id), fresh(id), fresh(id));
+    lam(n, lam(f, lam(x, app(var(f), app(app(var(n), var(f)), var(x))))))
+}
+
+pub fn cases() -> Vec<Case> {
+    let mut result = vec![];
+    let id = &mut 0;
+
+    // (λk. add (k 1 0) (k 2 0)) (λx. λy. x)
+    // the same binder is instantiated twice before either body is used
+    let k = fresh(id);
+    let first = app(app(var(k), numeral(1, id)), numeral(0, id));
+    let second = app(app(var(k), numeral(2, id)), numeral(0, id));
+    let body = app(app(add(id), first), second);
+    result.push(Case {
+        name: "binder applied twice",
+        tree: app(lam(k, body), constant(id)),
+        value: 3
+    });
+
+    // (λk. k 1 (λp. k 2 (λq. add (p 0) (q 0)))) (λx. λs. s (λy. x))
+    // the abstraction built by the first instance of k is only applied
+    // after the second instance has been created
+    let (k, p, q) = (fresh(id), fresh(id), fresh(id));
+    let (x, s, y) = (fresh(id), fresh(id), fresh(id));
+    let sum = app(app(add(id), app(var(p), numeral(0, id))), app(var(q), numeral(0, id)));
+    let inner = app(app(var(k), numeral(2, id)), lam(q, sum));
+    let body = app(app(var(k), numeral(1, id)), lam(p, inner));
+    let wrap = lam(x, lam(s, app(var(s), lam(y, var(x)))));
+    result.push(Case {
+        name: "escaping instances",
+        tree: app(lam(k, body), wrap),
+        value: 3
+    });
+
+    // 2 succ 1, the inner abstractions of succ outlive both applications
+    let tree = app(app(numeral(2, id), succ(id)), numeral(1, id));
+    result.push(Case {
+        name: "numeral iterating succ",
+        tree,
+        value: 3
+    });
+
+    // (λx. λx. x) 1 2
+    let x = fresh(id);
+    let tree = app(app(lam(x, lam(x, var(x))), numeral(1, id)), numeral(2, id));
+    result.push(Case {
+        name: "shadowed binder",
+        tree,
+        value: 2
+    });
+
+    // (λf. add (f 1) (f 2)) succ
+    // a shared abstraction is contracted while its other use is still live
+    let f = fresh(id);
+    let body = app(app(add(id), app(var(f), numeral(1, id))), app(var(f), numeral(2, id)));
+    result.push(Case {
+        name: "shared redex",
+        tree: app(lam(f, body), succ(id)),
+        value: 5
+    });
+
+    // (λf. add (f 1 0) (f 0 2)) (λa. λb. add a b)
+    // the inner binder of a shared abstraction has to be copied as well
+    let (f, a, b) = (fresh(id), fresh(id), fresh(id));
+    let first = app(app(var(f), numeral(1, id)), numeral(0, id));
+    let second = app(app(var(f), numeral(0, id)), numeral(2, id));
+    let body = app(app(add(id), first), second);
+    let plus = lam(a, lam(b, app(app(add(id), var(a)), var(b))));
+    result.push(Case {
+        name: "shared nested binder",
+        tree: app(lam(f, body), plus),
+        value: 3
+    });
+
+    // (λn. n n) 2 succ 0, the abstraction is its own argument
+    let n = fresh(id);
+    let square = app(lam(n, app(var(n), var(n))), numeral(2, id));
+    let tree = app(app(square, succ(id)), numeral(0, id));
+    result.push(Case {
+        name: "self applied numeral",
+        tree,
+        value: 4
+    });
+
+    // (λf. f 2) (λn. add n 1)
+    // the occurrences of n sit in arguments below their own binder, which
+    // is itself inside an argument
+    let (f, n) = (fresh(id), fresh(id));
+    let inner = lam(n, app(app(add(id), var(n)), numeral(1, id)));
+    result.push(Case {
+        name: "binder inside argument",
+        tree: app(lam(f, app(var(f), numeral(2, id))), inner),
+        value: 3
+    });
+
+    // (λx. add (add x x) x) 1, occurrences at different levels share x
+    let x = fresh(id);
+    let body = app(app(add(id), app(app(add(id), var(x)), var(x))), var(x));
+    result.push(Case {
+        name: "occurrences at different levels",
+        tree: app(lam(x, body), numeral(1, id)),
+        value: 3
+    });
+
+    // (λx. λy. x) 2 (3 succ 0), the second argument is thrown away
+    let erased = app(app(numeral(3, id), succ(id)), numeral(0, id));
+    result.push(Case {
+        name: "erased argument",
+        tree: app(app(constant(id), numeral(2, id)), erased),
+        value: 2
+    });
+
+    // 2 3, the normal form 9 is only reached through shared applications
+    result.push(Case {
+        name: "numeral exponent",
+        tree: app(numeral(2, id), numeral(3, id)),
+        value: 9
+    });
+
+    result
+}
+
+// Returns the number of failing strategy and case combinations
+pub fn run(strategies : &mut [Box<dyn Strategy>], cases : &[Case]) -> usize {
+    let mut failures = 0;
+    for case in cases.iter() {
+        for strategy in strategies.iter_mut() {
+            if !strategy.normalizes() { continue; }
+            strategy.build(&case.tree);
+            let output = strategy.reduce();
+            if output != Some(case.value) {
+                failures += 1;
+                println!("FAIL {}: {} gave {:?}, expected {}",
+                    case.name, strategy.name(), output, case.value);
+            }
+        }
+    }
+    failures
+}
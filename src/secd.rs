@@ -0,0 +1,128 @@
+use std::rc::Rc;
+use std::mem;
+
+use crate::tree::Tree;
+use crate::strategy::Strategy;
+use crate::trace;
+use crate::value::{self, Neutral};
+
+// Landin's SECD machine over de Bruijn indices. Applications are compiled
+// onto the control list as argument, function and an explicit apply
+// instruction, calling a closure saves the current stack, environment and
+// control on the dump. Like the CEK machine, abstractions are read back by
+// running their body with the bound variable mapped to a neutral value.
+
+type Value<'a> = value::Value<&'a Tree>;
+type Env<'a> = value::Env<Value<'a>>;
+
+enum Instruction<'a> {
+    Term(&'a Tree),
+    Apply
+}
+
+struct Dump<'a> {
+    stack : Vec<Value<'a>>,
+    env : Rc<Env<'a>>,
+    control : Vec<Instruction<'a>>
+}
+
+#[derive(Debug)]
+pub struct Secd {
+    tree : Tree,
+    steps : usize
+}
+
+impl Secd {
+    pub fn new() -> Secd {
+        Secd { tree: Tree::Var(0), steps: 0 }
+    }
+
+    fn run<'a>(&mut self, tree : &'a Tree, env : Rc<Env<'a>>) -> Value<'a> {
+        let mut stack : Vec<Value<'a>> = vec![];
+        let mut env = env;
+        let mut control = vec![Instruction::Term(tree)];
+        let mut dump : Vec<Dump<'a>> = vec![];
+        loop {
+            self.steps += 1;
+            if trace::enabled() {
+                let (rule, text) = match control.last() {
+                    Some(Instruction::Term(tree)) => match tree {
+                        Tree::Var(_) => ("var", tree.to_indexed_string()),
+                        Tree::Abs(_, _) => ("abs", tree.to_indexed_string()),
+                        Tree::App(_, _) => ("app", tree.to_indexed_string())
+                    },
+                    Some(Instruction::Apply) => ("apply", String::from("ap")),
+                    None if dump.is_empty() => ("halt", String::new()),
+                    None => ("return", String::new())
+                };
+                trace::record(rule, Env::len(&env), dump.len(), || text);
+            }
+            match control.pop() {
+                Some(Instruction::Term(Tree::Var(index))) => {
+                    stack.push(Env::lookup(&env, *index));
+                },
+                Some(Instruction::Term(Tree::Abs(_, body))) => {
+                    stack.push(Value::Closure(body, env.clone()));
+                },
+                Some(Instruction::Term(Tree::App(left, right))) => {
+                    // control is popped from the end so the argument runs first
+                    control.push(Instruction::Apply);
+                    control.push(Instruction::Term(left));
+                    control.push(Instruction::Term(right));
+                },
+                Some(Instruction::Apply) => {
+                    let function = stack.pop().expect("Impossible machine state.");
+                    let argument = stack.pop().expect("Impossible machine state.");
+                    match function {
+                        Value::Closure(body, closure_env) => {
+                            dump.push(Dump {
+                                stack: mem::take(&mut stack),
+                                env: mem::replace(&mut env, Rc::new(Env::Cons(argument, closure_env))),
+                                control: mem::replace(&mut control, vec![Instruction::Term(body)])
+                            });
+                        },
+                        Value::Neutral(neutral) => {
+                            stack.push(Value::Neutral(Rc::new(Neutral::App(neutral, argument))));
+                        }
+                    }
+                },
+                None => {
+                    let result = stack.pop().expect("Impossible machine state.");
+                    if let Some(saved) = dump.pop() {
+                        stack = saved.stack;
+                        env = saved.env;
+                        control = saved.control;
+                        stack.push(result);
+                    } else {
+                        return result;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Strategy for Secd {
+    fn build(&mut self, tree : &Tree) {
+        self.tree = Tree::fix_indices(tree.clone());
+        self.steps = 0;
+    }
+
+    fn reduce(&mut self) -> Option<u64> {
+        let tree = mem::replace(&mut self.tree, Tree::Var(0));
+        let value = self.run(&tree, Rc::new(Env::Nil));
+        let result = value::readback(value, 0, &mut |body, env, fresh| {
+            self.run(body, Rc::new(Env::Cons(fresh, env)))
+        }).convert();
+        self.tree = tree;
+        result
+    }
+
+    fn name(&self) -> String {
+        String::from("secd machine")
+    }
+
+    fn steps(&self) -> Option<usize> {
+        Some(self.steps)
+    }
+}
@@ -0,0 +1,108 @@
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Optional snapshot sink for the graph strategies. Between `start` and `stop`
+// every reduction step renders the whole graph to a numbered DOT file in the
+// given directory, labelled with the rule that fired and the id of the
+// redex, so the files can be rendered one by one into a flip-book. `stop`
+// writes an index of the steps to `steps.json` next to them.
+
+// Snapshots are taken per thread, like the recorder they go to
+thread_local! {
+    static ENABLED : Cell<bool> = const { Cell::new(false) };
+    static SINK : RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+struct Entry {
+    step : usize,
+    rule : String,
+    redex : Option<usize>,
+    file : String
+}
+
+struct Recorder {
+    dir : PathBuf,
+    entries : Vec<Entry>,
+    error : Option<io::Error>
+}
+
+pub fn enabled() -> bool {
+    ENABLED.with(Cell::get)
+}
+
+pub fn start(dir : &Path) {
+    SINK.with(|sink| {
+        *sink.borrow_mut() = Some(Recorder {
+            dir: dir.to_path_buf(),
+            entries: vec![],
+            error: None
+        });
+    });
+    ENABLED.with(|enabled| enabled.set(true));
+}
+
+// Returns the number of snapshots written, the directory is only created
+// once the first one is
+pub fn stop() -> io::Result<usize> {
+    ENABLED.with(|enabled| enabled.set(false));
+    let recorder = SINK.with(|sink| sink.borrow_mut().take());
+    let recorder = match recorder {
+        Some(recorder) => recorder,
+        None => return Ok(0)
+    };
+    if let Some(error) = recorder.error {
+        return Err(error);
+    }
+    if recorder.entries.is_empty() {
+        return Ok(0);
+    }
+    let mut index = String::from("[\n");
+    for (i, entry) in recorder.entries.iter().enumerate() {
+        let redex = match entry.redex {
+            Some(redex) => redex.to_string(),
+            None => String::from("null")
+        };
+        index.push_str(&format!("  {{\"step\": {}, \"rule\": \"{}\", \"redex\": {}, \"file\": \"{}\"}}",
+            entry.step, entry.rule, redex, entry.file));
+        index.push_str(if i + 1 < recorder.entries.len() { ",\n" } else { "\n" });
+    }
+    index.push_str("]\n");
+    fs::write(recorder.dir.join("steps.json"), index)?;
+    Ok(recorder.entries.len())
+}
+
+// The graph is only rendered while snapshots are being recorded. The DOT
+// source has to start with its `digraph` line, the step label goes after it.
+pub fn record(rule : &str, redex : Option<usize>, graph : impl FnOnce() -> String) {
+    if !enabled() { return; }
+    SINK.with(|sink| {
+        let mut sink = sink.borrow_mut();
+        let recorder = match sink.as_mut() {
+            Some(recorder) => recorder,
+            None => return
+        };
+        if recorder.error.is_some() { return; }
+        let step = recorder.entries.len();
+        let file = format!("step-{:05}.dot", step);
+        let label = match redex {
+            Some(redex) => format!("step {}: {} at {}", step, rule, redex),
+            None => format!("step {}: {}", step, rule)
+        };
+        let graph = graph();
+        let (header, rest) = graph.split_at(graph.find('\n').map_or(graph.len(), |i| i + 1));
+        let contents = format!("{}    label=\"{}\";\n    labelloc=t;\n{}", header, label, rest);
+        let written = fs::create_dir_all(&recorder.dir)
+            .and_then(|_| fs::write(recorder.dir.join(&file), contents));
+        match written {
+            Ok(()) => recorder.entries.push(Entry {
+                step,
+                rule: String::from(rule),
+                redex,
+                file
+            }),
+            Err(error) => recorder.error = Some(error)
+        }
+    });
+}
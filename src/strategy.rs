@@ -4,4 +4,22 @@ pub trait Strategy {
     fn build(&mut self, tree : &Tree);
     fn reduce(&mut self) -> Option<u64>;
     fn name(&self) -> String;
+
+    // Number of machine transitions taken by the last reduction, if the
+    // strategy keeps count
+    fn steps(&self) -> Option<usize> {
+        None
+    }
+
+    // Largest number of live graph nodes during the last reduction, if the
+    // strategy reduces a graph
+    fn nodes(&self) -> Option<usize> {
+        None
+    }
+
+    // Whether reduce reads back a full normal form, strategies that stop at
+    // weak head normal form can not be checked against the expected numeral
+    fn normalizes(&self) -> bool {
+        true
+    }
 }
@@ -0,0 +1,136 @@
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+// Optional trace sink for the abstract machines. Machines report every
+// configuration they pass through with `record`, which only does any work
+// between `start` and `stop` so the benchmarks are not affected. Traces are
+// written as tab separated values with one configuration per line.
+
+// The flag lives next to the sink it guards, recording on one thread never
+// turns it on for another
+thread_local! {
+    static ENABLED : Cell<bool> = const { Cell::new(false) };
+    static SINK : RefCell<Vec<Config>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub step : usize,
+    pub rule : String,
+    pub env : usize,
+    pub depth : usize,
+    // live graph nodes, only recorded by the graph strategies
+    pub nodes : Option<usize>,
+    pub control : String
+}
+
+#[derive(Debug)]
+pub struct Trace {
+    configs : Vec<Config>
+}
+
+pub fn enabled() -> bool {
+    ENABLED.with(Cell::get)
+}
+
+pub fn start() {
+    SINK.with(|sink| sink.borrow_mut().clear());
+    ENABLED.with(|enabled| enabled.set(true));
+}
+
+pub fn stop() -> Trace {
+    ENABLED.with(|enabled| enabled.set(false));
+    let configs = SINK.with(|sink| sink.replace(vec![]));
+    Trace { configs }
+}
+
+// The control is only rendered when a trace is being recorded
+pub fn record(rule : &str, env : usize, depth : usize, control : impl FnOnce() -> String) {
+    push(rule, env, depth, None, control);
+}
+
+// Graph reductions have no environment or stack, they report the number of
+// live nodes after the step instead
+pub fn record_nodes(rule : &str, nodes : usize, control : impl FnOnce() -> String) {
+    push(rule, 0, 0, Some(nodes), control);
+}
+
+fn push(rule : &str, env : usize, depth : usize, nodes : Option<usize>, control : impl FnOnce() -> String) {
+    if !enabled() { return; }
+    SINK.with(|sink| {
+        let mut sink = sink.borrow_mut();
+        let step = sink.len() + 1;
+        sink.push(Config {
+            step,
+            rule: String::from(rule),
+            env,
+            depth,
+            nodes,
+            control: control()
+        });
+    });
+}
+
+fn show(nodes : Option<usize>) -> String {
+    nodes.map_or(String::from("-"), |nodes| nodes.to_string())
+}
+
+impl Trace {
+    pub fn is_empty(&self) -> bool {
+        self.configs.is_empty()
+    }
+
+    pub fn write(&self, path : &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "step\trule\tenv\tdepth\tnodes\tcontrol")?;
+        for config in self.configs.iter() {
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}",
+                config.step, config.rule, config.env, config.depth, show(config.nodes), config.control)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(path : &Path) -> io::Result<Trace> {
+        let invalid = |line : usize| io::Error::new(io::ErrorKind::InvalidData,
+            format!("malformed trace entry on line {}", line));
+        let input = BufReader::new(File::open(path)?);
+        let mut configs = vec![];
+        for (i, line) in input.lines().enumerate().skip(1) {
+            let line = line?;
+            let fields : Vec<_> = line.splitn(6, '\t').collect();
+            if fields.len() != 6 { return Err(invalid(i + 1)); }
+            let nodes = match fields[4] {
+                "-" => None,
+                nodes => Some(nodes.parse().map_err(|_| invalid(i + 1))?)
+            };
+            configs.push(Config {
+                step: fields[0].parse().map_err(|_| invalid(i + 1))?,
+                rule: String::from(fields[1]),
+                env: fields[2].parse().map_err(|_| invalid(i + 1))?,
+                depth: fields[3].parse().map_err(|_| invalid(i + 1))?,
+                nodes,
+                control: String::from(fields[5])
+            });
+        }
+        Ok(Trace { configs })
+    }
+
+    // Pretty print every configuration, or only those up to step `stop`
+    pub fn replay(&self, stop : Option<usize>) {
+        println!("{:>8}  {:<10} {:>5} {:>6} {:>6}  control", "step", "rule", "env", "depth", "nodes");
+        for config in self.configs.iter() {
+            if let Some(stop) = stop {
+                if config.step > stop { break; }
+            }
+            println!("{:>8}  {:<10} {:>5} {:>6} {:>6}  {}",
+                config.step, config.rule, config.env, config.depth, show(config.nodes), config.control);
+        }
+        if let Some(stop) = stop {
+            if stop < self.configs.len() {
+                println!("stopped at step {} of {}", stop, self.configs.len());
+            }
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use std::cmp::max;
+use std::fmt;
+use std::rc::Rc;
 
 
 #[derive(Debug, Clone)]
@@ -93,3 +95,73 @@ impl Tree {
         }
     }
 }
+
+impl Tree {
+    // Graphviz rendering with one node per constructor
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph tree {\n    node [fontname=\"monospace\"];\n");
+        self.to_dot_helper(&mut 0, &mut out);
+        out.push_str("}\n");
+        out
+    }
+
+    fn to_dot_helper(&self, next : &mut usize, out : &mut String) -> usize {
+        let id = *next;
+        *next += 1;
+        match self {
+            Tree::Var(x) => {
+                out.push_str(&format!("    n{} [label=\"x{}\", shape=plaintext];\n", id, x));
+            },
+            Tree::Abs(x, body) => {
+                out.push_str(&format!("    n{} [label=\"λx{}\", shape=circle];\n", id, x));
+                let body = body.to_dot_helper(next, out);
+                out.push_str(&format!("    n{} -> n{};\n", id, body));
+            },
+            Tree::App(left, right) => {
+                out.push_str(&format!("    n{} [label=\"@\", shape=circle];\n", id));
+                let left = left.to_dot_helper(next, out);
+                let right = right.to_dot_helper(next, out);
+                out.push_str(&format!("    n{} -> n{} [label=\"fun\"];\n", id, left));
+                out.push_str(&format!("    n{} -> n{} [label=\"arg\"];\n", id, right));
+            }
+        }
+        id
+    }
+}
+
+impl fmt::Display for Tree {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Tree::Var(x) => write!(f, "x{}", x),
+            Tree::Abs(id, body) => write!(f, "λx{}. {}", id, body),
+            Tree::App(left, right) => write!(f, "({} {})", left, right)
+        }
+    }
+}
+
+// A tree with reference counted children, so machines can hold on to
+// subterms without copying them
+#[derive(Debug)]
+pub enum Code {
+    Var(usize),
+    Abs(usize, Rc<Code>),
+    App(Rc<Code>, Rc<Code>)
+}
+
+impl Code {
+    pub fn from(tree : &Tree) -> Rc<Code> {
+        Rc::new(match tree {
+            Tree::Var(x) => Code::Var(*x),
+            Tree::Abs(id, body) => Code::Abs(*id, Code::from(body)),
+            Tree::App(left, right) => Code::App(Code::from(left), Code::from(right))
+        })
+    }
+
+    pub fn to_tree(&self) -> Tree {
+        match self {
+            Code::Var(x) => Tree::Var(*x),
+            Code::Abs(id, body) => Tree::Abs(*id, Box::new(body.to_tree())),
+            Code::App(left, right) => Tree::App(Box::new(left.to_tree()), Box::new(right.to_tree()))
+        }
+    }
+}
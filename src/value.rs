@@ -0,0 +1,82 @@
+use std::rc::Rc;
+
+use crate::tree::Tree;
+
+// Linked list environments over de Bruijn indices and the semantic values of
+// the normalizing machines. A value is either a closure, pairing whatever
+// code the machine runs with its environment, or a neutral term over the
+// levels of the variables of abstractions we reduce under.
+
+pub enum Env<T> {
+    Nil,
+    Cons(T, Rc<Env<T>>)
+}
+
+impl<T> Env<T> {
+    pub fn len(env : &Rc<Env<T>>) -> usize {
+        let mut env = env;
+        let mut result = 0;
+        while let Env::Cons(_, rest) = &**env {
+            result += 1;
+            env = rest;
+        }
+        result
+    }
+}
+
+impl<T : Clone> Env<T> {
+    pub fn lookup(env : &Rc<Env<T>>, index : usize) -> T {
+        let mut env = env;
+        let mut index = index;
+        loop {
+            match &**env {
+                Env::Nil => panic!("Free variables are not supported."),
+                Env::Cons(value, rest) => {
+                    if index == 0 { return value.clone(); }
+                    index -= 1;
+                    env = rest;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum Value<C> {
+    Closure(C, Rc<Env<Value<C>>>),
+    Neutral(Rc<Neutral<C>>)
+}
+
+pub enum Neutral<C> {
+    Var(usize),
+    App(Rc<Neutral<C>>, Value<C>)
+}
+
+// Binders are named by their level so the result is a named tree. `enter`
+// runs the code of a closure in its environment extended with the neutral
+// value of the bound variable.
+pub fn readback<C, F>(value : Value<C>, level : usize, enter : &mut F) -> Tree
+    where C : Clone, F : FnMut(C, Rc<Env<Value<C>>>, Value<C>) -> Value<C>
+{
+    match value {
+        Value::Closure(code, env) => {
+            let fresh = Value::Neutral(Rc::new(Neutral::Var(level)));
+            let body = enter(code, env, fresh);
+            Tree::Abs(level, Box::new(readback(body, level + 1, enter)))
+        },
+        Value::Neutral(neutral) => readback_neutral(&neutral, level, enter)
+    }
+}
+
+fn readback_neutral<C, F>(neutral : &Neutral<C>, level : usize, enter : &mut F) -> Tree
+    where C : Clone, F : FnMut(C, Rc<Env<Value<C>>>, Value<C>) -> Value<C>
+{
+    match neutral {
+        Neutral::Var(x) => Tree::Var(*x),
+        Neutral::App(left, right) => {
+            let left = readback_neutral(left, level, enter);
+            let right = readback(right.clone(), level, enter);
+            Tree::App(Box::new(left), Box::new(right))
+        }
+    }
+}